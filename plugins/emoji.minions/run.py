@@ -34,6 +34,7 @@ else:
             'title': desc,
             'icon': 'character::{}'.format(emoji),
             'data': emoji,
+            'action': 'copy',
         })
 
 data.append({
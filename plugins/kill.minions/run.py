@@ -5,23 +5,25 @@
 # @Last Modified by:   BlahGeek
 # @Last Modified time: 2018-03-13
 
+import sys
 import json
 import psutil
 
 
-def display_process(process):
+def display_process(process, signal):
     return {
         'title': ' '.join(process.cmdline()),
         'subtitle': '{}, CPU {:.1f}%, MEM {:.1f}%, {}'
                     .format(process.pid, process.cpu_percent(),
                             process.memory_percent(), process.status()),
-        'action': 'kill {}'.format(process.pid),
+        'action': 'kill {} {}'.format(signal, process.pid),
     }
 
 
 if __name__ == '__main__':
+    signal = sys.argv[1] if len(sys.argv) > 1 else '-15'
     processes = list(psutil.process_iter())
     processes.sort(key=lambda p: (p.cpu_percent(), p.memory_percent()),
                    reverse=True)
-    print(json.dumps([display_process(p) for p in processes],
+    print(json.dumps([display_process(p, signal) for p in processes],
                      indent=4))
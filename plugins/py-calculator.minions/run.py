@@ -6,8 +6,21 @@ import json
 from asteval import Interpreter
 
 
-expr = sys.argv[1]
-print(json.dumps([{
-    'title': str(Interpreter()(expr)),
-    'subtitle': expr,
-}]))
+def calculate(expr):
+    return [{
+        'title': str(Interpreter()(expr)),
+        'subtitle': expr,
+        'action': 'copy',
+    }]
+
+
+# long-lived process using JSON-RPC protocol, see doc/plugin.md
+for line in sys.stdin:
+    request = json.loads(line)
+    response = {'jsonrpc': '2.0', 'id': request['id']}
+    try:
+        response['result'] = calculate(request['params'].get('arg', ''))
+    except Exception as e:
+        response['error'] = {'code': -32000, 'message': str(e)}
+    print(json.dumps(response))
+    sys.stdout.flush()
@@ -14,9 +14,11 @@ import subprocess
 def lookup(word, fuzzy=False):
     if fuzzy:
         word = '/' + word
-    output = subprocess.check_output(['sdcv', '--utf8-output', '--utf8-input',
-                                      '-n', word],
-                                     universal_newlines=True)
+    args = ['sdcv', '--utf8-output', '--utf8-input', '-n']
+    dictionary = os.getenv('MINIONS_OPTION_DICTIONARY')
+    if dictionary:
+        args += ['-u', dictionary]
+    output = subprocess.check_output(args + [word], universal_newlines=True)
     result = []
     item = []
     for line in output.splitlines():
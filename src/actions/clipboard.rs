@@ -82,6 +82,11 @@ impl ClipboardHistoryAction {
     }
 }
 
+/// Clipboard history requires an initialized GTK (with a display)
+pub fn available() -> bool {
+    gtk::is_initialized()
+}
+
 pub fn get(config: &Config) -> Item {
     let action = ClipboardHistoryAction::new(config);
     Item {
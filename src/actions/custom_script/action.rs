@@ -1,25 +1,73 @@
 extern crate serde_json;
-extern crate shlex;
 
-use std::path::PathBuf;
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::mcore::errors::*;
-use crate::mcore::action::{ActionResult, Action};
+use crate::mcore::action::{ActionResult, Action, CancelToken, RunContext};
+use crate::mcore::item::Item;
+use crate::actions::utils::subprocess;
 
 use super::item::{ScriptOutputFormat, ScriptItem};
+use super::rpc::RpcProcess;
+use super::plugin::PluginInfo;
+
+/// Per-run information passed to the script,
+/// as environment variables (`exec` protocol) or `context` param (`jsonrpc` protocol)
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct RunEnv {
+    /// Title and data of the selected item
+    pub title: String,
+    pub data: Option<String>,
+    /// Title and data of the item which outputs the selected item, if any
+    pub parent_title: Option<String>,
+    pub parent_data: Option<String>,
+    pub reference: Option<String>,
+    pub filter_text: Option<String>,
+}
+
+impl RunEnv {
+    fn apply(&self, cmd: &mut Command) {
+        cmd.env("MINIONS_ITEM_TITLE", &self.title);
+        let optional_vars = [
+            ("MINIONS_ITEM_DATA", &self.data),
+            ("MINIONS_PARENT_TITLE", &self.parent_title),
+            ("MINIONS_PARENT_DATA", &self.parent_data),
+            ("MINIONS_REFERENCE", &self.reference),
+            ("MINIONS_FILTER_TEXT", &self.filter_text),
+        ];
+        for (key, value) in optional_vars.iter() {
+            if let Some(value) = value {
+                cmd.env(key, value);
+            }
+        }
+    }
+}
 
 pub struct ScriptAction {
-    pub script_dir: PathBuf,
+    pub plugin: Arc<PluginInfo>,
+
+    /// Title and data of the item owning this action
+    pub item_title: String,
+    pub item_data: Option<String>,
+    /// Title and data of the item which outputs the item
+    pub parent_title: Option<String>,
+    pub parent_data: Option<String>,
 
     pub action: String,
     pub action_output_format: ScriptOutputFormat,
+    pub action_output_streaming: bool,
 
     pub action_run_bare: bool,
     pub action_run_arg: bool,
     pub action_run_realtime: bool,
 
     pub action_suggest_arg_scope: Option<String>,
+    pub timeout: Option<Duration>,
+
+    /// Plugin process to send requests to, if using JSON-RPC protocol
+    pub rpc: Option<Arc<RpcProcess>>,
 }
 
 impl Action for ScriptAction {
@@ -27,6 +75,8 @@ impl Action for ScriptAction {
     fn runnable_arg(&self) -> bool { self.action_run_arg }
     fn runnable_arg_realtime(&self) -> bool { self.action_run_realtime }
 
+    fn timeout(&self) -> Option<Duration> { self.timeout }
+
     fn suggest_arg_scope(&self) -> Option<&str> {
         match self.action_suggest_arg_scope {
             Some(ref s) => Some(&s),
@@ -35,15 +85,37 @@ impl Action for ScriptAction {
     }
 
     fn run_bare (&self) -> ActionResult {
-        self.run_action(None, "bare")
+        self.run_bare_cancellable(&CancelToken::new(), &RunContext::default())
     }
 
     fn run_arg(&self, text: &str) -> ActionResult {
-        self.run_action(Some(text), "text")
+        self.run_arg_cancellable(text, &CancelToken::new(), &RunContext::default())
     }
 
     fn run_arg_realtime(&self, text: &str) -> ActionResult {
-        self.run_action(Some(text), "realtime")
+        self.run_arg_realtime_cancellable(text, &CancelToken::new(), &RunContext::default())
+    }
+
+    fn run_bare_cancellable(&self, token: &CancelToken, context: &RunContext) -> ActionResult {
+        self.run_action(None, "bare", token, context)
+    }
+
+    fn run_arg_cancellable(&self, text: &str, token: &CancelToken, context: &RunContext) -> ActionResult {
+        self.run_action(Some(text), "text", token, context)
+    }
+
+    fn run_arg_realtime_cancellable(&self, text: &str, token: &CancelToken, context: &RunContext) -> ActionResult {
+        self.run_action(Some(text), "realtime", token, context)
+    }
+
+    fn run_bare_streaming(&self, sink: &dyn Fn(Vec<Item>), token: &CancelToken,
+                          context: &RunContext) -> ActionResult {
+        self.run_action_streaming(None, "bare", sink, token, context)
+    }
+
+    fn run_arg_streaming(&self, text: &str, sink: &dyn Fn(Vec<Item>), token: &CancelToken,
+                         context: &RunContext) -> ActionResult {
+        self.run_action_streaming(Some(text), "text", sink, token, context)
     }
 }
 
@@ -84,28 +156,102 @@ fn parse_plain_text (output: &[u8]) -> Result<Vec<ScriptItem>> {
     Ok(ret)
 }
 
-impl ScriptAction {
+/// Parse output of streaming actions incrementally
+/// Items are separated by `\0` for escaped text,
+/// and by lines for plain text and JSON (either an item object or an array of items per line)
+struct StreamParser {
+    format: ScriptOutputFormat,
+    buf: Vec<u8>,
+}
 
-    fn run_action (&self, arg: Option<&str>, typ: &str) -> ActionResult {
-        // TODO: support some special commands, like copy, open, etc.
-        let cmdline = shlex::split(&self.action);
-        if cmdline.is_none() {
-            bail!("Invalid action command");
+impl StreamParser {
+    fn new(format: ScriptOutputFormat) -> StreamParser {
+        StreamParser { format, buf: Vec::new() }
+    }
+
+    fn parse_record(&self, record: &[u8]) -> Result<Vec<ScriptItem>> {
+        match self.format {
+            ScriptOutputFormat::EscapedText => parse_escaped_text(record),
+            ScriptOutputFormat::PlainText => parse_plain_text(record),
+            ScriptOutputFormat::Json => {
+                let line = String::from_utf8_lossy(record);
+                let line = line.trim();
+                if line.is_empty() {
+                    Ok(Vec::new())
+                } else if line.starts_with('[') {
+                    parse_json(line.as_bytes())
+                } else {
+                    let item: ScriptItem = serde_json::from_str(line)
+                        .map_err(|e| Error::with_chain(e, "Error parsing JSON"))?;
+                    Ok(vec![item])
+                }
+            },
+        }
+    }
+
+    /// Feed more output, return items completed by it
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ScriptItem>> {
+        let sep = match self.format {
+            ScriptOutputFormat::EscapedText => 0u8,
+            _ => '\n' as u8,
+        };
+        self.buf.extend_from_slice(chunk);
+        let mut ret = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|x| *x == sep) {
+            let record: Vec<u8> = self.buf.drain(..pos + 1).collect();
+            ret.extend(self.parse_record(&record[..pos])?);
         }
+        Ok(ret)
+    }
+
+    /// Parse the remaining output
+    fn finish(self) -> Result<Vec<ScriptItem>> {
+        self.parse_record(&self.buf)
+    }
+}
+
+impl ScriptAction {
 
-        let cmdline = cmdline.unwrap();
-        let mut cmd = Command::new(&cmdline[0]);
-        if cmdline.len() > 1 {
-            cmd.args(&cmdline[1..]);
+    fn run_env(&self, context: &RunContext) -> RunEnv {
+        RunEnv {
+            title: self.item_title.clone(),
+            data: self.item_data.clone(),
+            parent_title: self.parent_title.clone(),
+            parent_data: self.parent_data.clone(),
+            reference: context.reference.clone(),
+            filter_text: context.filter_text.clone(),
         }
+    }
+
+    fn command(&self, arg: Option<&str>, typ: &str, context: &RunContext) -> Result<Command> {
+        let mut cmd = self.plugin.command(&self.action)?;
         if let Some(arg) = arg {
             cmd.arg(arg);
         }
-        cmd.current_dir(&self.script_dir);
         cmd.env("MINIONS_RUN_TYPE", typ);
+        self.run_env(context).apply(&mut cmd);
+        Ok(cmd)
+    }
+
+    fn into_items(&self, items: Vec<ScriptItem>) -> Vec<Item> {
+        items.into_iter().map(|x| x.into_item(&self.plugin, Some(self))).collect()
+    }
+
+    fn run_action (&self, arg: Option<&str>, typ: &str, token: &CancelToken, context: &RunContext) -> ActionResult {
+        if let Some(ref rpc) = self.rpc {
+            let method = match typ {
+                "bare" => "run_bare",
+                "realtime" => "run_arg_realtime",
+                _ => "run_arg",
+            };
+            let items = rpc.call(method, &self.action, arg, &self.run_env(context), token)?;
+            return Ok(self.into_items(items));
+        }
+
+        let mut cmd = self.command(arg, typ, context)?;
         debug!("Running script action: {:?}", cmd);
 
-        let output = cmd.output()?.stdout;
+        let output = subprocess::output_cancellable(&mut cmd, token)?;
         let items =
             if output.len() == 0 {
                 Vec::new()
@@ -117,6 +263,96 @@ impl ScriptAction {
                 }?
             };
 
-        Ok(items.into_iter().map(|x| x.into_item(&self.script_dir)).collect())
+        Ok(self.into_items(items))
+    }
+
+    fn run_action_streaming(&self, arg: Option<&str>, typ: &str,
+                            sink: &dyn Fn(Vec<Item>), token: &CancelToken, context: &RunContext) -> ActionResult {
+        if !self.action_output_streaming || self.rpc.is_some() {
+            return self.run_action(arg, typ, token, context);
+        }
+        let mut cmd = self.command(arg, typ, context)?;
+        debug!("Running script action (streaming): {:?}", cmd);
+
+        let mut parser = StreamParser::new(self.action_output_format.clone());
+        let mut parse_error: Option<Error> = None;
+        subprocess::run_cancellable(&mut cmd, token, |chunk| {
+            if parse_error.is_some() {
+                return;
+            }
+            match parser.feed(chunk) {
+                Ok(items) => if items.len() > 0 {
+                    sink(self.into_items(items));
+                },
+                Err(error) => parse_error = Some(error),
+            }
+        })?;
+        if let Some(error) = parse_error {
+            return Err(error);
+        }
+
+        Ok(self.into_items(parser.finish()?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_parser_test() {
+        let mut parser = StreamParser::new(ScriptOutputFormat::Json);
+        assert!(parser.feed(b"{\"title\": \"a\"}\n{\"title\"").unwrap().len() == 1);
+        let items = parser.feed(b": \"b\"}\n[{\"title\": \"c\"}, {\"title\": \"d\"}]").unwrap();
+        assert_eq!(items.iter().map(|x| x.title.clone()).collect::<Vec<_>>(), vec!["b".to_string()]);
+        assert_eq!(parser.finish().unwrap().len(), 2);
+
+        let mut parser = StreamParser::new(ScriptOutputFormat::EscapedText);
+        let items = parser.feed(b"title:a\x01badge:x\x00title:b").unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "a");
+        assert_eq!(items[0].badge, Some("x".into()));
+        assert_eq!(parser.finish().unwrap()[0].title, "b");
+
+        let mut parser = StreamParser::new(ScriptOutputFormat::PlainText);
+        assert_eq!(parser.feed(b"a\nb\nc").unwrap().len(), 2);
+        assert_eq!(parser.finish().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn script_env_test() {
+        let plugin = Arc::new(PluginInfo {
+            name: "test".into(),
+            dir: std::env::temp_dir(),
+            state_dir: std::env::temp_dir().join(format!("minions-env-test-{}", std::process::id())),
+            config: "{}".into(),
+            option_envs: Vec::new(),
+            rpc_processes: Default::default(),
+        });
+        let action = ScriptAction {
+            plugin: plugin.clone(),
+            item_title: "child".into(),
+            item_data: Some("child-data".into()),
+            parent_title: Some("parent".into()),
+            parent_data: None,
+            action: "sh -c 'echo $MINIONS_ITEM_DATA,$MINIONS_PARENT_TITLE,$MINIONS_REFERENCE,$MINIONS_PLUGIN_NAME'".into(),
+            action_output_format: ScriptOutputFormat::PlainText,
+            action_output_streaming: false,
+            action_run_bare: true,
+            action_run_arg: false,
+            action_run_realtime: false,
+            action_suggest_arg_scope: None,
+            timeout: None,
+            rpc: None,
+        };
+        let context = RunContext {
+            reference: Some("ref".into()),
+            filter_text: None,
+        };
+        let items = action.run_bare_cancellable(&CancelToken::new(), &context).unwrap();
+        assert_eq!(items[0].title, "child-data,parent,ref,test");
+
+        let _ = std::fs::remove_dir(&plugin.state_dir);
     }
 }
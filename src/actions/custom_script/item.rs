@@ -1,9 +1,13 @@
-use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::mcore::item::Item;
+use crate::mcore::action::Action;
 
 use super::action::ScriptAction;
+use super::rpc::RpcProcess;
+use super::special;
+use super::plugin::PluginInfo;
 use super::parser::parse_icon;
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -14,6 +18,16 @@ pub enum ScriptOutputFormat {
     PlainText,
 }
 
+/// How to communicate with the action command
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptProtocol {
+    /// Run the command for each request, passing argument as command line argument
+    Exec,
+    /// Keep the command running, sending requests via stdin and reading results from stdout
+    Jsonrpc,
+}
+
 #[derive(Deserialize, Clone)]
 #[serde(default = "ScriptItem::default")]
 pub struct ScriptItem {
@@ -25,13 +39,18 @@ pub struct ScriptItem {
     pub priority: i32,
 
     pub action: Option<String>,
+    pub protocol: ScriptProtocol,
     pub action_output_format: ScriptOutputFormat,
+    /// Whether items are emitted (and should be displayed) one by one
+    pub action_output_streaming: bool,
 
     pub action_run_bare: bool,
     pub action_run_arg: bool,
     pub action_run_realtime: bool,
 
     pub action_suggest_arg_scope: Option<String>,
+    /// Timeout (in milliseconds) of running the action, overriding `action_timeout` in config
+    pub timeout: Option<u64>,
 
     pub requirements: Vec<String>,
 }
@@ -46,35 +65,56 @@ impl ScriptItem {
             data: None,
             priority: -20,
             action: None,
+            protocol: ScriptProtocol::Exec,
             action_output_format: ScriptOutputFormat::Json,
+            action_output_streaming: false,
             action_run_bare: true,
             action_run_arg: false,
             action_run_realtime: true,
             action_suggest_arg_scope: None,
+            timeout: None,
             requirements: Vec::new(),
         }
     }
 
-    pub fn into_item(self, script_dir: &Path) -> Item {
+    /// `parent` is the action which outputs this item, if any
+    /// Items of a JSON-RPC plugin (and items returned by them) share its process
+    pub fn into_item(self, plugin: &Arc<PluginInfo>, parent: Option<&ScriptAction>) -> Item {
+
+        let content = self.data.as_ref().unwrap_or(&self.title).clone();
+        let special = self.action.as_ref().and_then(|x| special::parse(x, &content));
 
-        let action : Option<ScriptAction> =
+        let action : Option<Arc<dyn Action + Sync + Send>> =
             match self.action {
                 None => None,
+                Some(_) if special.is_some() => special,
                 Some(action) =>
-                    Some( ScriptAction {
-                        script_dir: script_dir.to_path_buf(),
+                    Some( Arc::new(ScriptAction {
+                        rpc: match (parent.and_then(|x| x.rpc.as_ref()), &self.protocol) {
+                            (Some(rpc), _) => Some(rpc.clone()),
+                            (None, ScriptProtocol::Jsonrpc) =>
+                                Some(RpcProcess::shared(&action, plugin)),
+                            (None, ScriptProtocol::Exec) => None,
+                        },
+                        plugin: plugin.clone(),
+                        item_title: self.title.clone(),
+                        item_data: self.data.clone(),
+                        parent_title: parent.map(|x| x.item_title.clone()),
+                        parent_data: parent.and_then(|x| x.item_data.clone()),
                         action: action,
                         action_output_format: self.action_output_format,
+                        action_output_streaming: self.action_output_streaming,
                         action_run_bare: self.action_run_bare,
                         action_run_arg: self.action_run_arg,
                         action_run_realtime: self.action_run_realtime,
                         action_suggest_arg_scope: self.action_suggest_arg_scope,
-                    } ),
+                        timeout: self.timeout.map(Duration::from_millis),
+                    } )),
             };
 
         let icon =
             match self.icon {
-                Some(ref s) => parse_icon(&s, script_dir),
+                Some(ref s) => parse_icon(&s, &plugin.dir),
                 None => None,
             };
 
@@ -86,10 +126,7 @@ impl ScriptItem {
             priority: self.priority,
             data: self.data,
             search_str: None,
-            action: match action {
-                Some(action) => Some(Arc::new(action)),
-                None => None,
-            },
+            action: action,
         }
 
     }
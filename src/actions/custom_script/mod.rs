@@ -2,54 +2,125 @@ pub mod parser;
 mod item;
 mod action;
 mod requirement;
+mod rpc;
+mod special;
+mod plugin;
+mod status;
 
 use toml;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::io::prelude::*;
 
 use crate::mcore::item::Item;
 use crate::mcore::config::Config;
 use crate::mcore::errors::*;
 use error_chain::ChainedError;
+use crate::actions::plugin_manager;
 
 use self::item::ScriptItem;
+use self::plugin::{PluginInfo, PluginOptions};
+use self::status::PluginStatus;
 
-fn get_item(script_dir: &Path) -> Result<Item> {
+/// Fields of item.toml shared by all items of the plugin
+#[derive(Deserialize)]
+struct PluginFile {
+    #[serde(default)]
+    requirements: Vec<String>,
+    /// Multiple items, instead of the single item defined at top level
+    #[serde(default)]
+    items: Vec<toml::value::Table>,
+}
 
-    let itemfile = script_dir.join("item.toml");
-    debug!("Reading script item: {:?}", itemfile);
+/// Parse requirement strings, an invalid one (e.g. a typo) fails loading the plugin instead of being ignored
+fn parse_requirements(requirements: &[String]) -> Result<Vec<(&str, requirement::Requirement)>> {
+    requirements.iter().map(|req_text| match requirement::Requirement::new(req_text) {
+        Some(req) => Ok((req_text.as_str(), req)),
+        None => bail!("Invalid requirement {:?}", req_text),
+    }).collect()
+}
 
-    let mut itemdata = String::new();
-    if let Ok(mut itemfile) = File::open(&itemfile) {
-        itemfile.read_to_string(&mut itemdata)?;
+fn check_requirements(requirements: &[(&str, requirement::Requirement)], script_dir: &Path) -> Result<()> {
+    for (req_text, req) in requirements.iter() {
+        req.check(script_dir)
+            .chain_err(|| format!("Requirement {} not met", req_text))?;
     }
-    let mut item : ScriptItem = toml::from_str(&itemdata)
-        .map_err(|e| Error::with_chain(e, "Failed parsing item.toml"))?;
+    Ok(())
+}
 
-    if item.title.len() == 0 {
-        bail!("Invalid item.toml: empty title");
+/// Parse items from content of item.toml
+///
+/// Top-level fields define the item, or if `[[items]]` is present,
+/// they are the defaults of each item (except `requirements`, which applies to the whole plugin)
+/// Items skipped for unmet requirements are recorded in `status`
+fn parse_items(itemdata: &str, script_dir: &Path, config: &Config,
+               status: &mut PluginStatus) -> Result<Vec<Item>> {
+    let file: PluginFile = toml::from_str(itemdata)
+        .map_err(|e| Error::with_chain(e, "Failed parsing item.toml"))?;
+    let mut base: toml::value::Table = toml::from_str(itemdata)
+        .map_err(|e| Error::with_chain(e, "Failed parsing item.toml"))?;
+    for key in ["items", "options", "requirements", "version"].iter() {
+        base.remove(*key);
     }
 
-    if item.badge.is_none() {
-        item.badge = Some("Script".into());
-    }
+    check_requirements(&parse_requirements(&file.requirements)?, script_dir)?;
 
-    for req_text in item.requirements.iter() {
-        if let Some(req) = requirement::Requirement::new(&req_text) {
-            if !req.check() {
-                bail!("Requirement not met: {:?}", req);
-            }
-        } else {
-            warn!("Invalid requirement string {}, ignore", req_text);
+    let options: PluginOptions = toml::from_str(itemdata)
+        .map_err(|e| Error::with_chain(e, "Failed parsing options in item.toml"))?;
+    let plugin = Arc::new(PluginInfo::new(script_dir, config, &options)?);
+
+    let tables = if file.items.is_empty() {
+        vec![base]
+    } else {
+        file.items.into_iter().map(|entry| {
+            let mut table = base.clone();
+            table.extend(entry);
+            table
+        }).collect()
+    };
+
+    let mut ret = Vec::new();
+    for table in tables.into_iter() {
+        let mut item: ScriptItem = toml::Value::Table(table).try_into()
+            .map_err(|e| Error::with_chain(e, "Failed parsing item in item.toml"))?;
+
+        if item.title.len() == 0 {
+            bail!("Invalid item.toml: empty title");
+        }
+
+        if item.badge.is_none() {
+            item.badge = Some("Script".into());
+        }
+
+        // requirements of single item, e.g. an optional tool
+        let requirements = parse_requirements(&item.requirements)?;
+        if let Err(error) = check_requirements(&requirements, script_dir) {
+            info!("Skipping item {:?}: {}", item.title, error.display_chain());
+            status.skipped.push((item.title.clone(), status::error_message(&error)));
+            continue;
         }
-    }
 
-    Ok(item.into_item(script_dir))
+        status.loaded.push(item.title.clone());
+        ret.push(item.into_item(&plugin, None));
+    }
+    Ok(ret)
 }
 
-pub fn get(config: &Config) -> Vec<Item> {
+fn get_items(script_dir: &Path, config: &Config, status: &mut PluginStatus) -> Result<Vec<Item>> {
 
+    let itemfile = script_dir.join("item.toml");
+    debug!("Reading script item: {:?}", itemfile);
+
+    let mut itemdata = String::new();
+    if let Ok(mut itemfile) = File::open(&itemfile) {
+        itemfile.read_to_string(&mut itemdata)?;
+    }
+    parse_items(&itemdata, script_dir, config, status)
+}
+
+/// Directories containing plugins
+fn plugin_dirs(config: &Config) -> Vec<PathBuf> {
     let mut plugin_dirs : Vec<PathBuf> = vec![
         Path::new("./plugins/").to_path_buf(),
         Path::new("./usr/share/minions-plugins/").to_path_buf(),
@@ -58,9 +129,29 @@ pub fn get(config: &Config) -> Vec<Item> {
     for dir in config.get::<Vec<String>>(&["core", "extra_plugin_directories"]).unwrap().iter() {
         plugin_dirs.push(Path::new(&dir).to_path_buf());
     }
+    plugin_dirs.push(config.get_filename(&["core", "plugin_install_directory"]).unwrap());
     plugin_dirs.dedup();
+    plugin_dirs
+}
+
+/// Plugin directories and each plugin in them, to reload when changed
+pub fn watch_paths(config: &Config) -> Vec<PathBuf> {
+    let mut ret = Vec::new();
+    for plugin_dir in plugin_dirs(config) {
+        if let Ok(entries) = plugin_dir.read_dir() {
+            ret.extend(entries.filter_map(|x| x.ok()).map(|x| x.path()).filter(|x| x.is_dir()));
+        }
+        ret.push(plugin_dir);
+    }
+    ret
+}
+
+pub fn get(config: &Config) -> Vec<Item> {
+
+    let plugin_dirs = plugin_dirs(config);
 
     let mut ret : Vec<Item> = Vec::new();
+    let mut statuses : Vec<PluginStatus> = Vec::new();
 
     for plugin_dir in plugin_dirs.iter() {
         info!("Loading custom action from {:?}", plugin_dir);
@@ -75,19 +166,121 @@ pub fn get(config: &Config) -> Vec<Item> {
         for entry in entries {
             if let Ok(entry) = entry {
                 let entry_path : PathBuf = entry.path();
-                match get_item(&entry_path) {
-                    Ok(x) => {
-                        info!("Loaded custom script at {:?}", entry_path);
-                        ret.push(x);
+                if !entry_path.is_dir() {
+                    continue;
+                }
+                let mut status = PluginStatus::new(&entry_path);
+                if plugin_manager::is_disabled(&entry_path) {
+                    info!("Plugin {:?} is disabled, ignore", entry_path);
+                    status.disabled = true;
+                    statuses.push(status);
+                    continue;
+                }
+                match get_items(&entry_path, config, &mut status) {
+                    Ok(items) => {
+                        info!("Loaded {} items from custom script at {:?}", items.len(), entry_path);
+                        ret.extend(items);
                     },
                     Err(error) => {
                         warn!("Unable to load custom script at {:?}: {}",
                               entry_path, error.display_chain());
+                        status.error = Some(status::error_message(&error));
                     }
                 }
+                statuses.push(status);
             }
         }
     }
+    ret.push(status::get(statuses));
     ret
 
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_items_test() {
+        let config = Config::with_layers(Path::new(""), None, &[]);
+        let script_dir = Path::new("/some/where/test.minions");
+
+        let mut status = PluginStatus::new(script_dir);
+        let items = parse_items(r#"
+            title = "Single"
+            action = "./run.sh"
+        "#, script_dir, &config, &mut status).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].badge, Some("Script".into()));
+
+        let items = parse_items(r#"
+            action = "./run.sh"
+            badge = "Test"
+            [[items]]
+            title = "First"
+            [[items]]
+            title = "Second"
+            action = "./run.sh second"
+            [[items]]
+            title = "Missing requirement"
+            requirements = ["exe:minions-nonexistent-command"]
+        "#, script_dir, &config, &mut status).unwrap();
+        assert_eq!(items.iter().map(|x| x.title.as_str()).collect::<Vec<_>>(),
+                   vec!["First", "Second"]);
+        assert!(items.iter().all(|x| x.badge == Some("Test".into()) && x.action.is_some()));
+        assert_eq!(status.loaded, vec!["Single", "First", "Second"]);
+        assert_eq!(status.skipped.len(), 1);
+        assert!(status.skipped[0].1.contains("not found in PATH"));
+
+        assert!(parse_items(r#"
+            [[items]]
+            subtitle = "No title"
+        "#, script_dir, &config, &mut status).is_err());
+        assert!(parse_items(r#"
+            title = "Missing requirement"
+            requirements = ["exe:minions-nonexistent-command"]
+        "#, script_dir, &config, &mut status).is_err());
+        for requirements in ["[\"exe bc\"]", "[\"pyhton:3.6\"]"].iter() {
+            let error = parse_items(&format!("title = \"Invalid requirement\"\n[[items]]\nrequirements = {}",
+                                             requirements), script_dir, &config, &mut status).err().unwrap();
+            assert!(error.to_string().contains("Invalid requirement"));
+        }
+    }
+
+    #[test]
+    fn parse_items_rpc_test() {
+        let script_dir = std::env::temp_dir().join(
+            format!("minions-parse-items-rpc-test-{}.minions", std::process::id()));
+        let state_dir = std::env::temp_dir().join(
+            format!("minions-parse-items-rpc-test-{}.state", std::process::id()));
+        let config = Config::with_layers(Path::new(""), None, &[
+            ("MINIONS_CORE__PLUGIN_STATE_DIRECTORY".into(), state_dir.to_string_lossy().into())]);
+        std::fs::create_dir_all(&script_dir).unwrap();
+        // reply with its pid
+        std::fs::write(script_dir.join("run.sh"), r#"
+while read -r line; do
+    id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+    printf '{"jsonrpc":"2.0","id":%s,"result":[{"title":"%s"}]}\n' "$id" "$$"
+done
+"#).unwrap();
+
+        let mut status = PluginStatus::new(&script_dir);
+        let items = parse_items(r#"
+            action = "sh run.sh"
+            protocol = "jsonrpc"
+            [[items]]
+            title = "First"
+            [[items]]
+            title = "Second"
+        "#, &script_dir, &config, &mut status).unwrap();
+        let pids: Vec<String> = items.iter()
+            .map(|x| x.action.as_ref().unwrap().run_bare().unwrap()[0].title.clone())
+            .collect();
+        assert_eq!(pids[0], pids[1]);
+
+        drop(items);
+        let _ = std::fs::remove_dir_all(&script_dir);
+        let _ = std::fs::remove_dir_all(&state_dir);
+    }
+}
@@ -0,0 +1,226 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-03
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate serde_json;
+extern crate shlex;
+
+use toml;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, Weak};
+
+use crate::mcore::config::Config;
+use crate::mcore::errors::*;
+
+use super::rpc::RpcProcess;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Array,
+}
+
+/// Configurable option declared in item.toml, e.g.
+/// ```toml
+/// [options.dictionary]
+/// type = "string"
+/// default = ""
+/// description = "Dictionary to use"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct PluginOption {
+    #[serde(rename = "type")]
+    pub typ: OptionType,
+    /// Required to be set by user if None
+    pub default: Option<toml::Value>,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Options declared in item.toml
+#[derive(Deserialize, Debug, Default)]
+pub struct PluginOptions {
+    #[serde(default)]
+    pub options: BTreeMap<String, PluginOption>,
+}
+
+impl PluginOption {
+    /// Check the type of value, convert integer to float if required
+    fn check(&self, value: toml::Value) -> Option<toml::Value> {
+        match (self.typ, value) {
+            (OptionType::String, v @ toml::Value::String(_)) => Some(v),
+            (OptionType::Integer, v @ toml::Value::Integer(_)) => Some(v),
+            (OptionType::Float, v @ toml::Value::Float(_)) => Some(v),
+            (OptionType::Float, toml::Value::Integer(x)) => Some(toml::Value::Float(x as f64)),
+            (OptionType::Boolean, v @ toml::Value::Boolean(_)) => Some(v),
+            (OptionType::Array, v @ toml::Value::Array(_)) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl PluginOptions {
+    /// Validate user's config against declared options, fill in defaults
+    pub fn resolve(&self, user: Option<toml::Value>) -> Result<toml::value::Table> {
+        let mut user = match user {
+            None => toml::value::Table::new(),
+            Some(toml::Value::Table(table)) => table,
+            Some(_) => bail!("Plugin config must be a table"),
+        };
+
+        let mut ret = toml::value::Table::new();
+        for (key, option) in self.options.iter() {
+            let value = match user.remove(key).or(option.default.clone()) {
+                Some(value) => value,
+                None => bail!("Option {:?} is required", key),
+            };
+            match option.check(value) {
+                Some(value) => { ret.insert(key.clone(), value); },
+                None => bail!("Invalid value of option {:?}, expected {:?}", key, option.typ),
+            }
+        }
+        for key in user.keys() {
+            warn!("Unknown plugin option {:?}, ignore", key);
+        }
+        Ok(ret)
+    }
+}
+
+/// Information of a script plugin, shared by all its items
+#[derive(Debug)]
+pub struct PluginInfo {
+    /// Directory name without `.minions` suffix
+    pub name: String,
+    pub dir: PathBuf,
+    /// Directory for the plugin to keep its state, created on demand
+    pub state_dir: PathBuf,
+    /// Config of the plugin (`[plugins.<name>]`), in JSON
+    pub config: String,
+    /// Scalar options as environment variables, e.g. `MINIONS_OPTION_DICTIONARY`
+    pub option_envs: Vec<(String, String)>,
+    /// JSON-RPC processes by command, shared by all items, see `RpcProcess::shared`
+    pub rpc_processes: Mutex<HashMap<String, Weak<RpcProcess>>>,
+}
+
+impl PluginInfo {
+
+    /// Read user's config of the plugin, validated against `options` if any declared
+    pub fn new(dir: &Path, config: &Config, options: &PluginOptions) -> Result<PluginInfo> {
+        let dirname = dir.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let name = dirname.trim_end_matches(".minions").to_string();
+        let user_config = config.get::<toml::Value>(&["plugins", &name]).ok();
+
+        let mut option_envs = Vec::new();
+        let plugin_config = if options.options.is_empty() {
+            user_config.unwrap_or(toml::Value::Table(toml::value::Table::new()))
+        } else {
+            let resolved = options.resolve(user_config)
+                .chain_err(|| format!("Invalid config of plugin {:?}", name))?;
+            for (key, value) in resolved.iter() {
+                let value = match value {
+                    toml::Value::String(x) => x.clone(),
+                    toml::Value::Array(_) => continue,
+                    x => x.to_string(),
+                };
+                option_envs.push((format!("MINIONS_OPTION_{}", key.to_uppercase().replace('-', "_")), value));
+            }
+            toml::Value::Table(resolved)
+        };
+
+        Ok(PluginInfo {
+            state_dir: config.get_filename(&["core", "plugin_state_directory"]).unwrap().join(&name),
+            name: name,
+            dir: dir.to_path_buf(),
+            config: serde_json::to_string(&plugin_config).unwrap(),
+            option_envs: option_envs,
+            rpc_processes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Build command from command line string,
+    /// running in the plugin directory with plugin's environment variables
+    pub fn command(&self, cmdline: &str) -> Result<Command> {
+        let cmdline = match shlex::split(cmdline) {
+            Some(ref x) if x.len() > 0 => x.clone(),
+            _ => bail!("Invalid action command"),
+        };
+
+        if let Err(error) = std::fs::create_dir_all(&self.state_dir) {
+            warn!("Unable to create state directory {:?}: {}", self.state_dir, error);
+        }
+
+        let mut cmd = Command::new(&cmdline[0]);
+        if cmdline.len() > 1 {
+            cmd.args(&cmdline[1..]);
+        }
+        cmd.current_dir(&self.dir);
+        cmd.env("MINIONS_PLUGIN_NAME", &self.name);
+        cmd.env("MINIONS_PLUGIN_CONFIG", &self.config);
+        cmd.env("MINIONS_STATE_DIR", &self.state_dir);
+        for (key, value) in self.option_envs.iter() {
+            cmd.env(key, value);
+        }
+        Ok(cmd)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_info_test() {
+        let state_dir = std::env::temp_dir().join(format!("minions-plugin-state-test-{}", std::process::id()));
+        let config = Config::with_layers(Path::new(""), None, &[
+            ("MINIONS_CORE__PLUGIN_STATE_DIRECTORY".into(), state_dir.to_string_lossy().into())]);
+        let plugin = PluginInfo::new(Path::new("/some/where/sdcv.minions"), &config,
+                                     &PluginOptions::default()).unwrap();
+        assert_eq!(plugin.name, "sdcv");
+        assert_eq!(plugin.config, "{}");
+        assert_eq!(plugin.state_dir, state_dir.join("sdcv"));
+
+        let cmd = plugin.command("./run.py 'a b'").unwrap();
+        let debug = format!("{:?}", cmd);
+        assert!(debug.contains("\"./run.py\" \"a b\""));
+        assert!(plugin.command("").is_err());
+
+        let _ = std::fs::remove_dir_all(&state_dir);
+    }
+
+    #[test]
+    fn plugin_options_test() {
+        let options: PluginOptions = toml::from_str(r#"
+            title = "Test"
+            [options.dictionary]
+            type = "string"
+            default = "wordnet"
+            [options.limit]
+            type = "float"
+            [options.fuzzy]
+            type = "boolean"
+            default = true
+        "#).unwrap();
+
+        assert!(options.resolve(None).is_err());
+
+        let user: toml::Value = toml::from_str("limit = 10\nunknown = 1").unwrap();
+        let resolved = options.resolve(Some(user)).unwrap();
+        assert_eq!(resolved.get("dictionary"), Some(&toml::Value::String("wordnet".into())));
+        assert_eq!(resolved.get("limit"), Some(&toml::Value::Float(10.0)));
+        assert_eq!(resolved.get("fuzzy"), Some(&toml::Value::Boolean(true)));
+        assert!(resolved.get("unknown").is_none());
+
+        let user: toml::Value = toml::from_str("limit = 1.5\nfuzzy = \"yes\"").unwrap();
+        assert!(options.resolve(Some(user)).is_err());
+    }
+}
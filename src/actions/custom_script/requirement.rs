@@ -2,16 +2,113 @@
 * @Author: BlahGeek
 * @Date:   2017-08-19
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-03-29
+* @Last Modified time: 2020-02-04
 */
 
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
+use dirs;
+
+use crate::mcore::errors::*;
+
+use super::status::error_message;
+
+/// Results of checks which run external commands are cached for this long
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref CHECK_CACHE: Mutex<HashMap<String, (Instant, std::result::Result<(), String>)>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Requirement {
+    /// `exe:<name or path>`
     Executable(String),
+    /// `py3:<package>`, python3 is available (and has the package, if not empty)
     Python3Package(String),
+    /// `py2:<package>`
     Python2Package(String),
+    /// `python:<version>`, minimum version of python3
+    PythonVersion(String),
+    /// `env:<name>`, environment variable is set and not empty
+    EnvVar(String),
+    /// `file:<path>`, file exists, relative to the plugin directory
+    File(String),
+    /// `minions:<version>`, minimum version of Minions
+    MinionsVersion(String),
+    /// `session:<x11|wayland>`, type of current graphical session
+    Session(String),
+    /// `cmd:<command>`, shell command runs successfully
+    Command(String),
+    /// `cmd-output:<text>:<command>`, shell command runs successfully and outputs the text
+    CommandOutput { text: String, command: String },
+}
+
+/// Parse version like "3.6.1" into numbers, ignoring non-numeric suffix of each part
+fn parse_version(version: &str) -> Vec<u64> {
+    version.trim().split('.')
+        .map(|part| {
+            let digits: String = part.chars().take_while(|x| x.is_ascii_digit()).collect();
+            digits.parse().unwrap_or(0)
+        })
+        .collect()
+}
+
+fn version_at_least(actual: &str, required: &str) -> bool {
+    let mut actual = parse_version(actual);
+    let mut required = parse_version(required);
+    let len = std::cmp::max(actual.len(), required.len());
+    actual.resize(len, 0);
+    required.resize(len, 0);
+    actual >= required
+}
+
+/// Type of current graphical session, "x11", "wayland" or "tty"
+fn session_type() -> String {
+    match std::env::var("XDG_SESSION_TYPE") {
+        Ok(ref x) if x == "x11" || x == "wayland" => x.clone(),
+        _ => {
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                "wayland".into()
+            } else if std::env::var_os("DISPLAY").is_some() {
+                "x11".into()
+            } else {
+                "tty".into()
+            }
+        },
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    match path.metadata() {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+/// Expand `~/`, and resolve relative path in the plugin directory
+fn resolve_path(path: &str, plugin_dir: &Path) -> PathBuf {
+    if path.starts_with("~/") {
+        if let Some(homedir) = dirs::home_dir() {
+            return homedir.join(&path[2..]);
+        }
+    }
+    plugin_dir.join(path)
+}
+
+/// Run command, return its stdout if succeeded
+fn run_output(cmd: &mut Command) -> Result<String> {
+    let output = cmd.stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        bail!("Command exited with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into())
 }
 
 impl Requirement {
@@ -29,51 +126,190 @@ impl Requirement {
             "exe" => Some(Requirement::Executable(arg.into())),
             "py3" => Some(Requirement::Python3Package(arg.into())),
             "py2" => Some(Requirement::Python2Package(arg.into())),
+            "python" => Some(Requirement::PythonVersion(arg.into())),
+            "env" => Some(Requirement::EnvVar(arg.into())),
+            "file" => Some(Requirement::File(arg.into())),
+            "minions" => Some(Requirement::MinionsVersion(arg.into())),
+            "session" => Some(Requirement::Session(arg.to_lowercase())),
+            "cmd" => Some(Requirement::Command(arg.into())),
+            "cmd-output" => {
+                let parts: Vec<&str> = arg.splitn(2, ":").collect();
+                if parts.len() < 2 {
+                    return None;
+                }
+                Some(Requirement::CommandOutput { text: parts[0].into(), command: parts[1].into() })
+            },
             _ => None,
         }
     }
 
-    fn check_executable(exe: &str) -> bool {
-        match Command::new("which").arg(exe)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status() {
-            Err(error) => {
-                warn!("Error running which: {}", error);
-                false
-            },
-            Ok(status) => {
-                status.success()
-            },
+    fn check_executable(exe: &str, plugin_dir: &Path) -> Result<()> {
+        if exe.contains('/') {
+            if !is_executable(&resolve_path(exe, plugin_dir)) {
+                bail!("{} is not executable", exe);
+            }
+            return Ok(());
+        }
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        if std::env::split_paths(&path).any(|dir| is_executable(&dir.join(exe))) {
+            Ok(())
+        } else {
+            bail!("{} not found in PATH", exe)
         }
     }
 
-    fn check_python_package(pkg: &str, python: &str) -> bool {
+    fn check_python_package(pkg: &str, python: &str) -> Result<()> {
         let mut cmd = Command::new(python);
         cmd.arg("-c")
            .arg("import pkg_resources as p;import sys;p.require(sys.argv[1])")
            .arg(pkg);
-        match cmd.stdout(Stdio::null()).stderr(Stdio::null()).status() {
-            Err(error) => {
-                warn!("Error checking python package {} for {}: {}", pkg, python, error);
-                false
-            },
-            Ok(status) => {
-                status.success()
-            },
+        run_output(&mut cmd).map(|_| ())
+            .chain_err(|| format!("Python package {:?} not found for {}", pkg, python))
+    }
+
+    fn check_python_version(version: &str) -> Result<()> {
+        let mut cmd = Command::new("python3");
+        cmd.arg("-c").arg("import sys;print('%d.%d.%d' % sys.version_info[:3])");
+        let actual = run_output(&mut cmd).chain_err(|| "Unable to get python3 version")?;
+        if !version_at_least(&actual, version) {
+            bail!("Python {} is required, found {}", version, actual.trim());
+        }
+        Ok(())
+    }
+
+    fn check_command(command: &str, text: Option<&str>, plugin_dir: &Path) -> Result<()> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command).current_dir(plugin_dir);
+        let output = run_output(&mut cmd).chain_err(|| format!("Command {:?} failed", command))?;
+        if let Some(text) = text {
+            if !output.contains(text) {
+                bail!("Output of command {:?} does not contain {:?}", command, text);
+            }
         }
+        Ok(())
     }
 
-    pub fn check(&self) -> bool {
-        let ret = match self {
+    fn check_uncached(&self, plugin_dir: &Path) -> Result<()> {
+        match self {
             &Requirement::Executable(ref name) =>
-                Requirement::check_executable(&name),
+                Requirement::check_executable(&name, plugin_dir),
             &Requirement::Python2Package(ref name) =>
                 Requirement::check_python_package(&name, "python2"),
             &Requirement::Python3Package(ref name) =>
                 Requirement::check_python_package(&name, "python3"),
-        };
-        trace!("Requirement {:?} check result: {}", &self, ret);
-        ret
+            &Requirement::PythonVersion(ref version) =>
+                Requirement::check_python_version(&version),
+            &Requirement::EnvVar(ref name) => {
+                match std::env::var_os(name) {
+                    Some(ref x) if !x.is_empty() => Ok(()),
+                    _ => bail!("Environment variable {} is not set", name),
+                }
+            },
+            &Requirement::File(ref path) => {
+                if resolve_path(path, plugin_dir).exists() {
+                    Ok(())
+                } else {
+                    bail!("File {} does not exist", path)
+                }
+            },
+            &Requirement::MinionsVersion(ref version) => {
+                let actual = env!("CARGO_PKG_VERSION");
+                if version_at_least(actual, version) {
+                    Ok(())
+                } else {
+                    bail!("Minions {} is required, this is {}", version, actual)
+                }
+            },
+            &Requirement::Session(ref session) => {
+                let actual = session_type();
+                if &actual == session {
+                    Ok(())
+                } else {
+                    bail!("Requires {} session, this is {}", session, actual)
+                }
+            },
+            &Requirement::Command(ref command) =>
+                Requirement::check_command(&command, None, plugin_dir),
+            &Requirement::CommandOutput { ref text, ref command } =>
+                Requirement::check_command(&command, Some(&text), plugin_dir),
+        }
+    }
+
+    /// Whether checking runs external commands, which is slow and should be cached
+    fn is_expensive(&self) -> bool {
+        match self {
+            &Requirement::Python2Package(_) | &Requirement::Python3Package(_) |
+            &Requirement::PythonVersion(_) | &Requirement::Command(_) |
+            &Requirement::CommandOutput{..} => true,
+            _ => false,
+        }
+    }
+
+    /// Check the requirement for plugin in `plugin_dir`, return the reason if not met
+    pub fn check(&self, plugin_dir: &Path) -> Result<()> {
+        if !self.is_expensive() {
+            return self.check_uncached(plugin_dir);
+        }
+
+        let key = format!("{:?}@{:?}", self, plugin_dir);
+        if let Some(&(ref time, ref result)) = CHECK_CACHE.lock().unwrap().get(&key) {
+            if time.elapsed() < CACHE_TTL {
+                trace!("Requirement {:?} check result (cached): {:?}", &self, result);
+                return result.clone().map_err(|e| e.into());
+            }
+        }
+
+        let result = self.check_uncached(plugin_dir).map_err(|e| error_message(&e));
+        trace!("Requirement {:?} check result: {:?}", &self, result);
+        CHECK_CACHE.lock().unwrap().insert(key, (Instant::now(), result.clone()));
+        result.map_err(|e| e.into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirement_parse_test() {
+        assert_eq!(Requirement::new("exe:bc"), Some(Requirement::Executable("bc".into())));
+        assert_eq!(Requirement::new("session:X11"), Some(Requirement::Session("x11".into())));
+        assert_eq!(Requirement::new("cmd-output:ok:echo a:b"),
+                   Some(Requirement::CommandOutput { text: "ok".into(), command: "echo a:b".into() }));
+        assert_eq!(Requirement::new("cmd-output:ok"), None);
+        assert_eq!(Requirement::new("unknown:x"), None);
+        assert_eq!(Requirement::new("exe"), None);
+    }
+
+    #[test]
+    fn requirement_check_test() {
+        let dir = std::env::temp_dir();
+        let check = |text: &str| Requirement::new(text).unwrap().check(&dir).is_ok();
+
+        assert!(check("exe:sh"));
+        assert!(check("exe:/bin/sh"));
+        assert!(!check("exe:minions-nonexistent-command"));
+        assert!(check("env:PATH"));
+        assert!(!check("env:MINIONS_NONEXISTENT_VARIABLE"));
+        assert!(check("file:/"));
+        assert!(!check("file:minions-nonexistent-file"));
+        assert!(check("minions:0.1"));
+        assert!(!check("minions:999"));
+        assert!(check("cmd:true"));
+        assert!(!check("cmd:false"));
+        assert!(check("cmd-output:hello:echo hello world"));
+        assert!(!check("cmd-output:bye:echo hello world"));
+
+        let error = Requirement::new("exe:minions-nonexistent-command").unwrap().check(&dir).unwrap_err();
+        assert!(error.to_string().contains("not found in PATH"));
+    }
+
+    #[test]
+    fn version_test() {
+        assert!(version_at_least("3.6.1", "3.6"));
+        assert!(version_at_least("3.10", "3.9"));
+        assert!(!version_at_least("3.5.9", "3.6"));
+        assert!(version_at_least("0.5.3-dev", "0.5.3"));
     }
 }
@@ -0,0 +1,257 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-01
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate serde_json;
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::mcore::action::CancelToken;
+use crate::mcore::errors::*;
+
+use super::action::RunEnv;
+use super::item::ScriptItem;
+use super::plugin::PluginInfo;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// JSON-RPC 2.0 request, one per line in plugin's stdin
+#[derive(Serialize)]
+struct RpcRequest<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: RpcParams<'a>,
+}
+
+#[derive(Serialize)]
+struct RpcParams<'a> {
+    /// Action of the item being run (the command itself for the plugin's item)
+    action: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arg: Option<&'a str>,
+    context: &'a RunEnv,
+}
+
+/// JSON-RPC 2.0 response, one per line in plugin's stdout
+/// Lines without matching id are ignored
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    result: Option<Vec<ScriptItem>>,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+struct RpcChild {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+}
+
+impl RpcChild {
+    fn is_alive(&mut self) -> bool {
+        match self.child.try_wait() {
+            Ok(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Drop for RpcChild {
+    fn drop(&mut self) {
+        debug!("Stopping plugin process {}", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct RpcState {
+    child: Option<RpcChild>,
+    next_id: u64,
+}
+
+/// Long-lived plugin process, talking JSON-RPC via stdin/stdout
+/// Started on first request, and restarted if it crashes or hangs
+pub struct RpcProcess {
+    command: String,
+    plugin: Arc<PluginInfo>,
+    state: Mutex<RpcState>,
+}
+
+impl RpcProcess {
+
+    pub fn new(command: &str, plugin: &Arc<PluginInfo>) -> RpcProcess {
+        RpcProcess {
+            command: command.into(),
+            plugin: plugin.clone(),
+            state: Mutex::new(RpcState { child: None, next_id: 0 }),
+        }
+    }
+
+    /// Process of `command` shared by all items of the plugin,
+    /// the same one is returned as long as any item still holds it
+    pub fn shared(command: &str, plugin: &Arc<PluginInfo>) -> Arc<RpcProcess> {
+        let mut processes = plugin.rpc_processes.lock().unwrap();
+        if let Some(process) = processes.get(command).and_then(|x| x.upgrade()) {
+            return process;
+        }
+        let process = Arc::new(RpcProcess::new(command, plugin));
+        processes.insert(command.into(), Arc::downgrade(&process));
+        process
+    }
+
+    fn spawn(&self) -> Result<RpcChild> {
+        let mut cmd = self.plugin.command(&self.command)?;
+        cmd.env("MINIONS_PROTOCOL", "jsonrpc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        let mut child = cmd.spawn()
+            .chain_err(|| format!("Unable to start plugin {:?}", self.command))?;
+        info!("Started plugin {:?} in {:?}, pid {}", self.command, self.plugin.dir, child.id());
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (send_ch, recv_ch) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if send_ch.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+        Ok(RpcChild { child, stdin, lines: recv_ch })
+    }
+
+    /// Send request and wait for the result
+    /// `method` is one of `run_bare`, `run_arg` and `run_arg_realtime`
+    pub fn call(&self, method: &str, action: &str, arg: Option<&str>, env: &RunEnv,
+                token: &CancelToken) -> Result<Vec<ScriptItem>> {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        let request = serde_json::to_string(&RpcRequest {
+            jsonrpc: "2.0",
+            id: id,
+            method: method,
+            params: RpcParams { action, arg, context: env },
+        }).unwrap();
+
+        let alive = match state.child {
+            Some(ref mut child) => child.is_alive(),
+            None => false,
+        };
+        if !alive {
+            if state.child.is_some() {
+                warn!("Plugin {:?} exited, restarting", self.command);
+            }
+            state.child = None;
+            state.child = Some(self.spawn()?);
+        }
+
+        let child = state.child.as_mut().unwrap();
+        trace!("Sending request to plugin: {}", request);
+        if let Err(error) = writeln!(child.stdin, "{}", request).and_then(|_| child.stdin.flush()) {
+            state.child = None;
+            return Err(Error::with_chain(error, "Unable to send request to plugin"));
+        }
+
+        loop {
+            match child.lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => {
+                    let response: RpcResponse = match serde_json::from_str(&line) {
+                        Ok(response) => response,
+                        Err(error) => {
+                            warn!("Invalid response from plugin {:?}: {}", self.command, error);
+                            continue;
+                        }
+                    };
+                    // response of previously cancelled request
+                    if response.id != Some(id) {
+                        debug!("Discarding outdated response {:?}", response.id);
+                        continue;
+                    }
+                    if let Some(error) = response.error {
+                        bail!("Plugin error: {}", error.message);
+                    }
+                    return Ok(response.result.unwrap_or_default());
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if token.is_timed_out() {
+                        // probably stuck, restart it next time
+                        state.child = None;
+                        bail!("Plugin timed out");
+                    }
+                    if token.is_cancelled() {
+                        bail!("Cancelled");
+                    }
+                },
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    state.child = None;
+                    bail!("Plugin exited unexpectedly");
+                },
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+while read -r line; do
+    case "$line" in
+        *'"arg":"exit"'*) exit 1;;
+    esac
+    id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+    echo 'not a response'
+    printf '{"jsonrpc":"2.0","id":%s,"result":[{"title":"%s"}]}\n' "$id" "$$"
+done
+"#;
+
+    #[test]
+    fn rpc_process_test() {
+        let script_dir = std::env::temp_dir().join(
+            format!("minions-rpc-test-{}", std::process::id()));
+        std::fs::create_dir_all(&script_dir).unwrap();
+        std::fs::write(script_dir.join("run.sh"), SCRIPT).unwrap();
+
+        let plugin = Arc::new(PluginInfo {
+            name: "test".into(),
+            dir: script_dir.clone(),
+            state_dir: script_dir.join("state"),
+            config: "{}".into(),
+            option_envs: Vec::new(),
+            rpc_processes: Default::default(),
+        });
+        let process = RpcProcess::new("sh run.sh", &plugin);
+        let token = CancelToken::new();
+        let env = RunEnv::default();
+        let pid = process.call("run_bare", "sh run.sh", None, &env, &token).unwrap()[0].title.clone();
+        let result = process.call("run_arg", "sh run.sh", Some("1"), &env, &token).unwrap();
+        assert_eq!(result[0].title, pid);
+
+        assert!(process.call("run_arg", "sh run.sh", Some("exit"), &env, &token).is_err());
+        let result = process.call("run_arg", "sh run.sh", Some("1"), &env, &token).unwrap();
+        assert_ne!(result[0].title, pid);
+
+        let _ = std::fs::remove_dir_all(&script_dir);
+    }
+}
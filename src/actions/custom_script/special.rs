@@ -0,0 +1,94 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-02
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+use std::sync::Arc;
+
+use crate::mcore::action::{Action, ActionResult, BuiltinAction};
+use crate::mcore::errors::*;
+use crate::actions::utils::{open, subprocess};
+
+struct OpenAction {
+    target: String,
+}
+
+impl Action for OpenAction {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        open::that(&self.target)
+            .map_err(|e| Error::with_chain(e, format!("Failed to open {}", self.target)))?;
+        Ok(Vec::new())
+    }
+}
+
+struct NotifyAction {
+    text: String,
+}
+
+impl Action for NotifyAction {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        subprocess::spawn("notify-send", &["Minions", &self.text])
+            .map_err(|e| Error::with_chain(e, "Failed to send notification"))?;
+        Ok(Vec::new())
+    }
+}
+
+/// Parse special action of script items, instead of running it as command:
+///
+/// - `copy:<text>`: copy text to clipboard (`copy` copies the item's data or title)
+/// - `open:<url>`: open url or path with default application
+/// - `quicksend:<text>`: quicksend text to other actions
+/// - `type:<text>`: type text into the previously focused window
+/// - `notify:<text>`: show desktop notification
+/// - `reload`: reload all actions
+pub fn parse(action: &str, content: &str) -> Option<Arc<dyn Action + Sync + Send>> {
+    if action == "copy" {
+        return Some(Arc::new(BuiltinAction::Copy(content.into())));
+    }
+    if action == "reload" {
+        return Some(Arc::new(BuiltinAction::Reload));
+    }
+
+    let parts: Vec<&str> = action.splitn(2, ':').collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    let arg: String = parts[1].into();
+    match parts[0] {
+        "copy" => Some(Arc::new(BuiltinAction::Copy(arg))),
+        "quicksend" => Some(Arc::new(BuiltinAction::Quicksend(arg))),
+        "type" => Some(Arc::new(BuiltinAction::Type(arg))),
+        "open" => Some(Arc::new(OpenAction { target: arg })),
+        "notify" => Some(Arc::new(NotifyAction { text: arg })),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_special_test() {
+        let builtin = |action: &str| parse(action, "content").and_then(|x| x.builtin());
+        assert_eq!(builtin("copy"), Some(BuiltinAction::Copy("content".into())));
+        assert_eq!(builtin("copy:a:b"), Some(BuiltinAction::Copy("a:b".into())));
+        assert_eq!(builtin("quicksend:hello"), Some(BuiltinAction::Quicksend("hello".into())));
+        assert_eq!(builtin("type:hello world"), Some(BuiltinAction::Type("hello world".into())));
+        assert_eq!(builtin("reload"), Some(BuiltinAction::Reload));
+
+        assert!(parse("open:https://example.com", "").unwrap().runnable_bare());
+        assert!(parse("notify:done", "").is_some());
+
+        assert!(parse("./run.py", "").is_none());
+        assert!(parse("python3 -c 'print(1)'", "").is_none());
+        assert!(parse("unknown:arg", "").is_none());
+    }
+}
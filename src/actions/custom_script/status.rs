@@ -0,0 +1,119 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-04
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-04
+*/
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::mcore::action::{Action, ActionResult};
+use crate::mcore::item::{Item, Icon};
+use crate::mcore::errors::*;
+
+/// Result of loading a plugin directory
+#[derive(Debug, Clone)]
+pub struct PluginStatus {
+    pub path: PathBuf,
+    /// Titles of loaded items
+    pub loaded: Vec<String>,
+    /// Titles of skipped items, with reasons
+    pub skipped: Vec<(String, String)>,
+    /// Reason of failing to load the whole plugin
+    pub error: Option<String>,
+    /// Disabled by the plugin manager, not loaded
+    pub disabled: bool,
+}
+
+impl PluginStatus {
+    pub fn new(path: &Path) -> PluginStatus {
+        PluginStatus {
+            path: path.to_path_buf(),
+            loaded: Vec::new(),
+            skipped: Vec::new(),
+            error: None,
+            disabled: false,
+        }
+    }
+
+    fn into_item(self) -> Item {
+        let (badge, icon, subtitle) = match self.error {
+            _ if self.disabled => ("Disabled", "toggle-off", "Disabled in plugin manager".into()),
+            Some(ref error) => ("Failed", "times", format!("Not loaded: {}", error)),
+            None => {
+                let mut subtitle = format!("Loaded: {}", self.loaded.join(", "));
+                for &(ref title, ref reason) in self.skipped.iter() {
+                    subtitle += &format!("; skipped {}: {}", title, reason);
+                }
+                if self.skipped.is_empty() {
+                    ("Loaded", "check", subtitle)
+                } else {
+                    ("Partial", "exclamation", subtitle)
+                }
+            },
+        };
+        Item {
+            title: self.path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default(),
+            subtitle: Some(subtitle),
+            badge: Some(badge.into()),
+            icon: Some(Icon::FontAwesome(icon.into())),
+            data: Some(self.path.to_string_lossy().into()),
+            .. Item::default()
+        }
+    }
+}
+
+/// Error and its causes in one line
+pub fn error_message(error: &Error) -> String {
+    error.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+struct PluginStatusAction {
+    statuses: Vec<PluginStatus>,
+}
+
+impl Action for PluginStatusAction {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        Ok(self.statuses.iter().map(|x| x.clone().into_item()).collect())
+    }
+}
+
+/// Item listing all discovered plugins, with why they were or weren't loaded
+pub fn get(statuses: Vec<PluginStatus>) -> Item {
+    let failed = statuses.iter().filter(|x| x.error.is_some()).count();
+    Item {
+        title: "Plugin Status".into(),
+        subtitle: Some(format!("{} plugins discovered, {} failed to load", statuses.len(), failed)),
+        badge: Some("Minions".into()),
+        priority: 100,
+        icon: Some(Icon::FontAwesome("puzzle-piece".into())),
+        action: Some(Arc::new(PluginStatusAction { statuses })),
+        .. Item::default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_status_test() {
+        let mut ok = PluginStatus::new(Path::new("/plugins/ok.minions"));
+        ok.loaded.push("Ok".into());
+        let mut failed = PluginStatus::new(Path::new("/plugins/failed.minions"));
+        failed.error = Some(error_message(&Error::with_chain(Error::from("exe not found"),
+                                                             "Requirement not met")));
+
+        let item = get(vec![ok, failed]);
+        assert_eq!(item.subtitle, Some("2 plugins discovered, 1 failed to load".into()));
+        let items = item.action.unwrap().run_bare().unwrap();
+        assert_eq!(items[0].title, "ok.minions");
+        assert_eq!(items[0].badge, Some("Loaded".into()));
+        assert_eq!(items[1].badge, Some("Failed".into()));
+        assert_eq!(items[1].subtitle, Some("Not loaded: Requirement not met: exe not found".into()));
+    }
+}
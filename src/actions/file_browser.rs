@@ -2,19 +2,23 @@
 * @Author: BlahGeek
 * @Date:   2017-06-17
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2020-01-17
+* @Last Modified time: 2020-02-13
 */
 
 use std::sync::Arc;
+use std::cell::RefCell;
 use std::path::{PathBuf, Path};
 
 use dirs;
 
 use crate::mcore::item::{Item, Icon};
-use crate::mcore::action::{Action, ActionResult};
+use crate::mcore::action::{Action, ActionResult, CancelToken, RunContext};
 use crate::mcore::config::Config;
 use crate::actions::utils::open;
 
+/// Number of entries sent at once when reading large directories
+const STREAMING_BATCH_SIZE: usize = 256;
+
 struct FileBrowserEntry {
     name: String,
     path: PathBuf,
@@ -65,35 +69,51 @@ impl Action for FileBrowserEntry {
     fn runnable_bare (&self) -> bool { true }
 
     fn run_bare (&self) -> ActionResult {
+        let ret = RefCell::new(Vec::new());
+        let last = self.run_bare_streaming(&|items| ret.borrow_mut().extend(items),
+                                           &CancelToken::new(), &RunContext::default())?;
+        let mut ret = ret.into_inner();
+        ret.extend(last);
+        Ok(ret)
+    }
+
+    fn run_bare_streaming (&self, sink: &dyn Fn(Vec<Item>), token: &CancelToken, _: &RunContext) -> ActionResult {
         if self.is_file {
             open::that(&self.path.to_string_lossy())?;
-            Ok(Vec::new())
-        } else {
-            let mut ret = Vec::new();
-
-            debug!("Reading dir: {:?}", self.path);
-            let entries = self.path.read_dir()?;
-            for entry in entries.into_iter() {
-                match entry {
-                    Ok(entry) => {
-                        if let Some(act) = FileBrowserEntry::new(entry.file_name().to_string_lossy().into(), entry.path()) {
-                            ret.push(act.into_item())
-                        }
-                    },
-                    Err(error) => {
-                        warn!("Read dir error: {}", error);
+            return Ok(Vec::new());
+        }
+
+        // send parent first so that it's available immediately
+        if let Some(parent) = self.path.parent() {
+            if let Some(act) = FileBrowserEntry::new("..".into(), parent.into()) {
+                let mut item = act.into_item();
+                item.priority = -100;
+                sink(vec![item]);
+            }
+        }
+
+        let mut ret = Vec::new();
+        debug!("Reading dir: {:?}", self.path);
+        let entries = self.path.read_dir()?;
+        for entry in entries.into_iter() {
+            if token.is_cancelled() {
+                bail!("Reading dir cancelled");
+            }
+            match entry {
+                Ok(entry) => {
+                    if let Some(act) = FileBrowserEntry::new(entry.file_name().to_string_lossy().into(), entry.path()) {
+                        ret.push(act.into_item())
                     }
+                },
+                Err(error) => {
+                    warn!("Read dir error: {}", error);
                 }
             }
-            if let Some(parent) = self.path.parent() {
-                if let Some(act) = FileBrowserEntry::new("..".into(), parent.into()) {
-                    let mut item = act.into_item();
-                    item.priority = -100;
-                    ret.push(item);
-                }
+            if ret.len() >= STREAMING_BATCH_SIZE {
+                sink(std::mem::replace(&mut ret, Vec::new()));
             }
-            Ok(ret)
         }
+        Ok(ret)
     }
 }
 
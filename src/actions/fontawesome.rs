@@ -1,8 +1,6 @@
-use crate::frontend::ui::FA_FONTS;
-
 use std::sync::Arc;
 use crate::mcore::action::{Action, ActionResult};
-use crate::mcore::item::{Item, Icon};
+use crate::mcore::item::{Item, Icon, FA_FONTS};
 use crate::mcore::config::Config;
 
 struct FontAwesome {}
@@ -2,7 +2,7 @@
 * @Author: BlahGeek
 * @Date:   2017-05-01
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-04-16
+* @Last Modified time: 2020-02-06
 */
 
 extern crate shlex;
@@ -12,7 +12,7 @@ use self::ini::Ini;
 
 use std::ffi::OsStr;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::mcore::action::{Action, ActionResult};
 use crate::mcore::item::{Item, Icon};
 use crate::mcore::config::Config;
@@ -165,3 +165,8 @@ pub fn get(config: &Config) -> Vec<Item> {
         }).collect()
 }
 
+/// Directories of desktop entries, to reload when changed
+pub fn watch_paths(config: &Config) -> Vec<PathBuf> {
+    config.get::<Vec<String>>(&["linux_desktop_entry", "directories"]).unwrap()
+        .into_iter().map(PathBuf::from).collect()
+}
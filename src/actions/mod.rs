@@ -2,10 +2,10 @@
 * @Author: BlahGeek
 * @Date:   2017-04-18
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-04-08
+* @Last Modified time: 2020-02-13
 */
 
-mod utils;
+pub(crate) mod utils;
 
 mod linux_desktop_entry;
 mod search_engine;
@@ -13,26 +13,77 @@ mod file_browser;
 mod custom_script;
 mod youdao;
 mod wolframalpha;
+#[cfg(feature = "gui")]
 mod clipboard;
 mod fontawesome;
 mod reload;
+pub mod plugin_manager;
+
+use std::path::PathBuf;
 
 use crate::mcore::config::Config;
 use crate::mcore::item::Item;
 
-pub fn get_action_items(config: &Config) -> Vec<Item> {
-    let mut ret : Vec<Item> = vec![];
+/// Source of action items, each can be reloaded separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    SearchEngine,
+    FileBrowser,
+    DesktopEntry,
+    CustomScript,
+    /// Other actions, which only depend on the config
+    Builtin,
+}
+
+impl Provider {
+    /// All providers, in the order of their items
+    pub const ALL: [Provider; 5] = [
+        Provider::SearchEngine,
+        Provider::FileBrowser,
+        Provider::DesktopEntry,
+        Provider::CustomScript,
+        Provider::Builtin,
+    ];
+}
 
-    ret.append(&mut search_engine::get(config));
-    ret.append(&mut file_browser::get(config));
-    ret.append(&mut linux_desktop_entry::get(config));
-    ret.append(&mut custom_script::get(config));
+pub fn get_provider_items(config: &Config, provider: Provider) -> Vec<Item> {
+    match provider {
+        Provider::SearchEngine => search_engine::get(config),
+        Provider::FileBrowser => file_browser::get(config),
+        Provider::DesktopEntry => linux_desktop_entry::get(config),
+        Provider::CustomScript => custom_script::get(config),
+        Provider::Builtin => {
+            let mut ret : Vec<Item> = vec![];
+            #[cfg(feature = "gui")]
+            {
+                if clipboard::available() {
+                    ret.push(clipboard::get(config));
+                }
+            }
+            ret.push(youdao::get(config));
+            ret.push(wolframalpha::get(config));
+            ret.push(reload::get(config));
+            ret.push(plugin_manager::get(config));
+            ret.push(fontawesome::get(config));
+            ret
+        },
+    }
+}
 
-    ret.push(clipboard::get(config));
-    ret.push(youdao::get(config));
-    ret.push(wolframalpha::get(config));
-    ret.push(reload::get(config));
-    ret.push(fontawesome::get(config));
+pub fn get_action_items(config: &Config) -> Vec<Item> {
+    Provider::ALL.iter()
+        .flat_map(|provider| get_provider_items(config, *provider))
+        .collect()
+}
 
+/// Files and directories whose changes affect items of the provider
+pub fn get_watch_paths(config: &Config) -> Vec<(PathBuf, Provider)> {
+    let mut ret = Vec::new();
+    for path in linux_desktop_entry::watch_paths(config) {
+        ret.push((path, Provider::DesktopEntry));
+    }
+    for path in custom_script::watch_paths(config) {
+        ret.push((path, Provider::CustomScript));
+    }
     ret
 }
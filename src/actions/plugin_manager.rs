@@ -0,0 +1,418 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-05
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+use toml;
+use std::fs;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::mcore::action::{Action, ActionResult, BuiltinAction};
+use crate::mcore::item::{Item, Icon};
+use crate::mcore::config::Config;
+use crate::mcore::errors::*;
+
+/// Marker file in the plugin directory, the plugin is not loaded if it exists
+pub const DISABLED_MARKER: &str = ".disabled";
+
+/// Whether the plugin in `dir` is disabled by `PluginManager`
+pub fn is_disabled(dir: &Path) -> bool {
+    dir.join(DISABLED_MARKER).exists()
+}
+
+/// Plugin installed by `PluginManager`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstalledPlugin {
+    /// Directory name, e.g. "sdcv.minions"
+    pub name: String,
+    /// `version` in item.toml
+    pub version: Option<String>,
+    pub enabled: bool,
+    pub path: PathBuf,
+}
+
+/// Resolve `link` (a symlink in `dir`) lexically, None if it points outside `root`
+fn resolve_link(root: &Path, dir: &Path, link: &Path) -> Option<PathBuf> {
+    if link.is_absolute() {
+        return None;
+    }
+    let mut ret = dir.to_path_buf();
+    for component in link.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => if !ret.pop() { return None },
+            Component::Normal(x) => ret.push(x),
+            _ => return None,
+        }
+    }
+    if ret.starts_with(root) { Some(ret) } else { None }
+}
+
+/// Copy directory `src` (inside `root`) recursively, skipping `.git`.
+/// Symlinks are recreated as symlinks, and rejected if they point outside `root`
+fn copy_dir(root: &Path, src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in src.read_dir()? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_symlink() {
+            let link = fs::read_link(&path)?;
+            if resolve_link(root, src, &link).is_none() {
+                bail!("Symlink {:?} points outside of the plugin", path);
+            }
+            std::os::unix::fs::symlink(&link, dst.join(entry.file_name()))
+                .chain_err(|| format!("Unable to copy {:?}", path))?;
+        } else if file_type.is_dir() {
+            copy_dir(root, &path, &dst.join(entry.file_name()))?;
+        } else if file_type.is_file() {
+            fs::copy(&path, dst.join(entry.file_name()))
+                .chain_err(|| format!("Unable to copy {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a private (0700) temporary directory with a random name
+fn make_temp_dir() -> Result<PathBuf> {
+    let template = std::env::temp_dir().join("minions-plugin-install-XXXXXX");
+    let mut buf = template.into_os_string().into_vec();
+    buf.push(0);
+    let ret = unsafe { libc::mkdtemp(buf.as_mut_ptr() as *mut libc::c_char) };
+    if ret.is_null() {
+        return Err(std::io::Error::last_os_error()).chain_err(|| "Unable to create temporary directory");
+    }
+    buf.pop();
+    Ok(PathBuf::from(OsString::from_vec(buf)))
+}
+
+/// Find the plugin (directory containing item.toml) in `dir` or its only subdirectory
+fn find_plugin_root(dir: &Path) -> Result<PathBuf> {
+    if dir.join("item.toml").is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let subdirs: Vec<PathBuf> = dir.read_dir()?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_dir() && x.join("item.toml").is_file())
+        .collect();
+    if subdirs.len() != 1 {
+        bail!("No plugin (item.toml) found in {:?}", dir);
+    }
+    Ok(subdirs[0].clone())
+}
+
+/// Name of installed plugin directory, from the source path
+fn plugin_name(source: &Path) -> String {
+    let mut name = source.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+    for ext in [".tar.gz", ".tar.xz", ".tar.bz2", ".tgz", ".tar", ".minions"].iter() {
+        if name.ends_with(ext) {
+            let len = name.len() - ext.len();
+            name.truncate(len);
+            break;
+        }
+    }
+    name + ".minions"
+}
+
+/// Install, update and remove plugins in the install directory (`core.plugin_install_directory`)
+pub struct PluginManager {
+    dir: PathBuf,
+}
+
+impl PluginManager {
+
+    pub fn new(dir: &Path) -> PluginManager {
+        PluginManager { dir: dir.to_path_buf() }
+    }
+
+    pub fn from_config(config: &Config) -> PluginManager {
+        PluginManager::new(&config.get_filename(&["core", "plugin_install_directory"]).unwrap())
+    }
+
+    /// Install plugin from a directory (e.g. git checkout) or tarball,
+    /// replace the installed one with the same name if `update`. Return the name
+    pub fn install(&self, source: &Path, update: bool) -> Result<String> {
+        if !source.exists() {
+            bail!("{:?} does not exist", source);
+        }
+
+        if source.is_dir() {
+            return find_plugin_root(source).and_then(|root| {
+                let name = plugin_name(&root);
+                self.install_dir(&root, &name, update).map(|_| name)
+            });
+        }
+
+        let extract_dir = make_temp_dir()?;
+        let result = Command::new("tar").arg("-xf").arg(source).arg("-C").arg(&extract_dir)
+            .status().chain_err(|| "Unable to run tar")
+            .and_then(|status| {
+                if !status.success() {
+                    bail!("Unable to extract {:?}: tar exited with {}", source, status);
+                }
+                find_plugin_root(&extract_dir)
+            })
+            .and_then(|root| {
+                // use the tarball's name if it's not in a directory
+                let name = plugin_name(if root == extract_dir { source } else { &root });
+                self.install_dir(&root, &name, update).map(|_| name)
+            });
+        let _ = fs::remove_dir_all(&extract_dir);
+        result
+    }
+
+    /// Copy `root` to a staging directory next to the target, then swap it in,
+    /// so that the installed version is kept if anything fails
+    fn install_dir(&self, root: &Path, name: &str, update: bool) -> Result<()> {
+        let target = self.dir.join(name);
+        let exists = fs::symlink_metadata(&target).is_ok();
+        if exists && !update {
+            bail!("Plugin {} is already installed, update it instead", name);
+        }
+        info!("Installing plugin {} from {:?}", name, root);
+        let staging = self.dir.join(format!(".{}.staging", name));
+        let old = self.dir.join(format!(".{}.old", name));
+        let _ = fs::remove_dir_all(&staging);
+        let _ = fs::remove_dir_all(&old);
+
+        let result = copy_dir(root, root, &staging).and_then(|_| {
+            if exists && is_disabled(&target) {
+                fs::write(staging.join(DISABLED_MARKER), "")?;
+            }
+            if exists {
+                fs::rename(&target, &old)
+                    .chain_err(|| format!("Unable to move old version of {}", name))?;
+            }
+            if let Err(err) = fs::rename(&staging, &target) {
+                if exists {
+                    let _ = fs::rename(&old, &target);
+                }
+                return Err(err).chain_err(|| format!("Unable to install {}", name));
+            }
+            Ok(())
+        });
+        let _ = fs::remove_dir_all(&staging);
+        let _ = fs::remove_dir_all(&old);
+        result
+    }
+
+    fn installed_path(&self, name: &str) -> Result<PathBuf> {
+        let name = if name.ends_with(".minions") { name.to_string() } else { format!("{}.minions", name) };
+        let path = self.dir.join(&name);
+        if name.contains('/') || !path.join("item.toml").is_file() {
+            bail!("Plugin {} is not installed", name);
+        }
+        Ok(path)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.installed_path(name)?;
+        info!("Removing plugin {:?}", path);
+        fs::remove_dir_all(&path).chain_err(|| format!("Unable to remove {:?}", path))
+    }
+
+    /// Enable or disable the plugin without deleting it
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        let marker = self.installed_path(name)?.join(DISABLED_MARKER);
+        if enabled {
+            if marker.exists() {
+                fs::remove_file(&marker)?;
+            }
+        } else {
+            fs::write(&marker, "")?;
+        }
+        Ok(())
+    }
+
+    /// All installed plugins, sorted by name
+    pub fn list(&self) -> Result<Vec<InstalledPlugin>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut ret: Vec<InstalledPlugin> = self.dir.read_dir()?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| x.join("item.toml").is_file())
+            .map(|path| {
+                let version = fs::read_to_string(path.join("item.toml")).ok()
+                    .and_then(|x| x.parse::<toml::Value>().ok())
+                    .and_then(|x| x.get("version").and_then(|v| v.as_str()).map(|v| v.to_string()));
+                InstalledPlugin {
+                    name: path.file_name().unwrap().to_string_lossy().into(),
+                    version: version,
+                    enabled: !is_disabled(&path),
+                    path: path,
+                }
+            })
+            .collect();
+        ret.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ret)
+    }
+}
+
+
+/// Item shown after plugins are changed, to reload all actions
+/// (which is also done by the file watcher, if enabled)
+fn reload_item(title: String) -> Item {
+    Item {
+        title: title,
+        subtitle: Some("Select to reload all actions".into()),
+        badge: Some("Minions".into()),
+        icon: Some(Icon::FontAwesome("cog".into())),
+        action: Some(Arc::new(BuiltinAction::Reload)),
+        .. Item::default()
+    }
+}
+
+struct TogglePluginAction {
+    manager: Arc<PluginManager>,
+    name: String,
+    enable: bool,
+}
+
+impl Action for TogglePluginAction {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        self.manager.set_enabled(&self.name, self.enable)?;
+        let state = if self.enable { "Enabled" } else { "Disabled" };
+        Ok(vec![reload_item(format!("{} {}", state, self.name))])
+    }
+}
+
+/// List installed plugins to enable or disable, or install plugin from the entered path
+struct PluginManagerAction {
+    manager: Arc<PluginManager>,
+}
+
+impl Action for PluginManagerAction {
+    fn runnable_bare(&self) -> bool { true }
+    fn runnable_arg(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        Ok(self.manager.list()?.into_iter().map(|plugin| {
+            Item {
+                title: plugin.name.clone(),
+                subtitle: Some(format!("Version {}, select to {}",
+                                       plugin.version.as_ref().map(|x| x.as_str()).unwrap_or("unknown"),
+                                       if plugin.enabled { "disable" } else { "enable" })),
+                badge: Some(if plugin.enabled { "Enabled" } else { "Disabled" }.into()),
+                icon: Some(Icon::FontAwesome(if plugin.enabled { "toggle-on" } else { "toggle-off" }.into())),
+                data: Some(plugin.path.to_string_lossy().into()),
+                action: Some(Arc::new(TogglePluginAction {
+                    manager: self.manager.clone(),
+                    name: plugin.name,
+                    enable: !plugin.enabled,
+                })),
+                .. Item::default()
+            }
+        }).collect())
+    }
+
+    fn run_arg(&self, text: &str) -> ActionResult {
+        let path = match text.trim() {
+            x if x.starts_with("~/") => dirs::home_dir().unwrap_or_default().join(&x[2..]),
+            x => PathBuf::from(x),
+        };
+        let name = self.manager.install(&path, true)?;
+        Ok(vec![reload_item(format!("Installed {}", name))])
+    }
+}
+
+pub fn get(config: &Config) -> Item {
+    Item {
+        title: "Manage Plugins".into(),
+        subtitle: Some("Enable or disable installed plugins, or enter path to install".into()),
+        badge: Some("Minions".into()),
+        priority: 100,
+        icon: Some(Icon::FontAwesome("puzzle-piece".into())),
+        action: Some(Arc::new(PluginManagerAction {
+            manager: Arc::new(PluginManager::from_config(config)),
+        })),
+        .. Item::default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_name_test() {
+        assert_eq!(plugin_name(Path::new("/a/sdcv.minions")), "sdcv.minions");
+        assert_eq!(plugin_name(Path::new("/a/sdcv")), "sdcv.minions");
+        assert_eq!(plugin_name(Path::new("/a/sdcv.tar.gz")), "sdcv.minions");
+    }
+
+    #[test]
+    fn plugin_manager_test() {
+        let root = std::env::temp_dir().join(format!("minions-plugin-manager-test-{}", std::process::id()));
+        let source = root.join("source").join("hello.minions");
+        fs::create_dir_all(source.join(".git")).unwrap();
+        fs::write(source.join("item.toml"), "title = \"Hello\"\nversion = \"1.0\"\n").unwrap();
+        fs::write(source.join("run.sh"), "echo hello").unwrap();
+
+        let manager = PluginManager::new(&root.join("installed"));
+        assert!(manager.list().unwrap().is_empty());
+
+        assert_eq!(manager.install(&root.join("source"), false).unwrap(), "hello.minions");
+        assert!(manager.install(&source, false).is_err());
+        let installed = root.join("installed").join("hello.minions");
+        assert!(installed.join("run.sh").is_file());
+        assert!(!installed.join(".git").exists());
+
+        manager.set_enabled("hello", false).unwrap();
+        fs::write(source.join("item.toml"), "title = \"Hello\"\nversion = \"1.1\"\n").unwrap();
+        manager.install(&source, true).unwrap();
+        assert_eq!(manager.list().unwrap(), vec![InstalledPlugin {
+            name: "hello.minions".into(),
+            version: Some("1.1".into()),
+            enabled: false,
+            path: installed.clone(),
+        }]);
+
+        // links inside the plugin are kept, links to outside are rejected without touching the installed one
+        std::os::unix::fs::symlink("run.sh", source.join("link.sh")).unwrap();
+        manager.install(&source, true).unwrap();
+        assert_eq!(fs::read_link(installed.join("link.sh")).unwrap(), Path::new("run.sh"));
+        std::os::unix::fs::symlink("../../..", source.join("escape")).unwrap();
+        assert!(manager.install(&source, true).is_err());
+        assert!(installed.join("run.sh").is_file());
+        assert!(!installed.join("escape").exists());
+        fs::remove_file(source.join("escape")).unwrap();
+
+        manager.set_enabled("hello.minions", true).unwrap();
+        assert!(manager.list().unwrap()[0].enabled);
+
+        // toggling from the list returns an item to reload, instead of reloading by itself
+        let action = PluginManagerAction { manager: Arc::new(PluginManager::new(&root.join("installed"))) };
+        let items = action.run_bare().unwrap();
+        let result = items[0].action.as_ref().unwrap().run_bare().unwrap();
+        assert_eq!(result[0].title, "Disabled hello.minions");
+        assert_eq!(result[0].action.as_ref().unwrap().builtin(), Some(BuiltinAction::Reload));
+        assert!(!manager.list().unwrap()[0].enabled);
+
+        assert!(manager.remove("../source").is_err());
+        manager.remove("hello").unwrap();
+        assert!(manager.list().unwrap().is_empty());
+
+        let tarball = root.join("hello.tar.gz");
+        assert!(Command::new("tar").arg("-czf").arg(&tarball).arg("-C").arg(&source).arg(".")
+                .status().unwrap().success());
+        assert_eq!(manager.install(&tarball, false).unwrap(), "hello.minions");
+        assert!(installed.join("run.sh").is_file());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
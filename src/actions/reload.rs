@@ -1,32 +1,17 @@
-extern crate nix;
-
 use std::sync::Arc;
 
-use crate::mcore::action::{Action, ActionResult};
+use crate::mcore::action::BuiltinAction;
 use crate::mcore::item::{Item, Icon};
 use crate::mcore::config::Config;
-use crate::mcore::errors::*;
-
-
-struct ReloadAction {}
-
-impl Action for ReloadAction {
-    fn runnable_bare(&self) -> bool { true }
-    fn run_bare(&self) -> ActionResult {
-        nix::sys::signal::kill(0, nix::sys::signal::SIGHUP)
-            .map_err(|e| Error::with_chain(e, "Failed to send SIGHUP to myself"))?;
-        Ok(Vec::new())
-    }
-}
 
 pub fn get(_: &Config) -> Item {
     Item {
         title: "Reload All Actions".into(),
-        subtitle: Some("Equivalent to `kill -HUP 0`".into()),
+        subtitle: Some("Equivalent to `minions ctl reload`".into()),
         badge: Some("Minions".into()),
         priority: 100,
         icon: Some(Icon::FontAwesome("cog".into())),
-        action: Some(Arc::new(ReloadAction{})),
+        action: Some(Arc::new(BuiltinAction::Reload)),
         .. Item::default()
     }
 }
@@ -2,8 +2,9 @@
 * @Author: BlahGeek
 * @Date:   2017-06-24
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2017-08-19
+* @Last Modified time: 2020-02-13
 */
 
 pub mod open;
 pub mod subprocess;
+pub mod typing;
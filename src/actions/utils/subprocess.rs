@@ -2,13 +2,22 @@
 * @Author: BlahGeek
 * @Date:   2017-07-07
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-04-08
+* @Last Modified time: 2020-01-28
 */
 
 extern crate nix;
+extern crate libc;
 
 use std::ffi::CString;
-use std::io::Result;
+use std::io::{Read, Result};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::mcore::action::CancelToken;
+use crate::mcore::errors;
 
 
 pub fn spawn(cmd: &str, args: &[&str]) -> Result<()> {
@@ -35,3 +44,105 @@ pub fn spawn(cmd: &str, args: &[&str]) -> Result<()> {
 
     Ok(())
 }
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Run command and return its stdout, like `Command::output`,
+/// but kill it (and its children) once `token` is cancelled
+pub fn output_cancellable(cmd: &mut Command, token: &CancelToken) -> errors::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    run_cancellable(cmd, token, |chunk| output.extend_from_slice(chunk))?;
+    Ok(output)
+}
+
+fn kill_cancelled(child: &mut Child, token: &CancelToken) -> errors::Result<()> {
+    debug!("Killing process {}", child.id());
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+    if token.is_timed_out() {
+        bail!("Process timed out");
+    }
+    bail!("Process cancelled");
+}
+
+/// Run command, feed its stdout to `on_output` chunk by chunk as soon as it's available,
+/// kill it (and its children) once `token` is cancelled
+pub fn run_cancellable<F>(cmd: &mut Command, token: &CancelToken, mut on_output: F) -> errors::Result<()>
+where F: FnMut(&[u8]) {
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null());
+    unsafe {
+        // new process group, so that all its children can be killed together
+        cmd.pre_exec(|| {
+            libc::setpgid(0, 0);
+            Ok(())
+        });
+    }
+
+    let mut child = cmd.spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let (send_ch, recv_ch) = mpsc::channel::<Vec<u8>>();
+    let reader = thread::spawn(move || -> Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stdout.read(&mut buf)?;
+            if n == 0 || send_ch.send(buf[..n].to_vec()).is_err() {
+                return Ok(());
+            }
+        }
+    });
+
+    // until stdout is closed
+    loop {
+        match recv_ch.recv_timeout(POLL_INTERVAL) {
+            Ok(chunk) => on_output(&chunk),
+            Err(mpsc::RecvTimeoutError::Timeout) => {},
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        if token.is_cancelled() {
+            return kill_cancelled(&mut child, token);
+        }
+    }
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            debug!("Process {} exited with {}", child.id(), status);
+            break;
+        }
+        if token.is_cancelled() {
+            return kill_cancelled(&mut child, token);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    match reader.join() {
+        Ok(result) => Ok(result?),
+        Err(_) => bail!("Unable to read process output"),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_cancellable_test() {
+        let output = output_cancellable(Command::new("echo").arg("hello"), &CancelToken::new()).unwrap();
+        assert_eq!(output, b"hello\n");
+
+        let token = CancelToken::with_timeout(Some(Duration::from_millis(100)));
+        let start = std::time::Instant::now();
+        assert!(output_cancellable(Command::new("sleep").arg("10"), &token).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn run_cancellable_test() {
+        let mut chunks = Vec::new();
+        run_cancellable(Command::new("sh").arg("-c").arg("echo a; sleep 0.2; echo b"),
+                        &CancelToken::new(), |chunk| chunks.push(chunk.to_vec())).unwrap();
+        assert_eq!(chunks, vec![b"a\n".to_vec(), b"b\n".to_vec()]);
+    }
+}
@@ -0,0 +1,30 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-13
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+use std::io::Result;
+
+use crate::actions::utils::subprocess;
+
+/// Seconds to wait before typing, for the window to be hidden
+/// and the focus to return to the previous window
+const DELAY: &str = "0.2";
+
+/// Whether running in a Wayland session, where xdotool does not work
+fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").map(|x| !x.is_empty()).unwrap_or(false)
+}
+
+/// Type text into the focused window after a short delay, in background,
+/// using `wtype` on Wayland or `xdotool` on X11
+pub fn type_text(text: &str) -> Result<()> {
+    let script = if is_wayland() {
+        format!("sleep {} && exec wtype -- \"$0\"", DELAY)
+    } else {
+        format!("sleep {} && exec xdotool type --clearmodifiers -- \"$0\"", DELAY)
+    };
+    subprocess::spawn("sh", &["-c", &script, text])
+}
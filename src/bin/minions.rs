@@ -2,15 +2,15 @@
 * @Author: BlahGeek
 * @Date:   2017-06-20
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2020-01-17
+* @Last Modified time: 2020-02-13
 */
 
 extern crate minions;
 extern crate toml;
 extern crate clap;
 extern crate nix;
-extern crate gtk;
 extern crate chrono;
+extern crate serde_json;
 
 extern crate log;
 extern crate fern;
@@ -18,8 +18,243 @@ extern crate fern;
 use dirs;
 
 use std::path::Path;
+use std::io::BufRead;
 
-use minions::frontend::app::MinionsApp;
+use minions::frontend::controller::{Controller, Notifier};
+use minions::frontend::dmenu::Dmenu;
+use minions::frontend::cli;
+use minions::frontend::ipc;
+use minions::frontend::instance::InstanceLock;
+use minions::mcore::config::Config;
+use minions::actions::plugin_manager::PluginManager;
+use minions::mcore::clipboard::Clipboard;
+
+#[cfg(feature = "gui")]
+fn run_gui(configfile: &Path, request: Option<&ipc::Request>) {
+    minions::frontend::gui::run(configfile, request);
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_: &Path, _: Option<&ipc::Request>) {
+    eprintln!("Minions is built without GTK frontend, try --tui");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "gui")]
+fn run_gui_once<F>(build: F)
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    minions::frontend::gui::run_once(build);
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui_once<F>(_: F)
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    eprintln!("Minions is built without GTK frontend, try --tui");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(configfile: &Path) {
+    use error_chain::ChainedError;
+    if let Err(error) = minions::frontend::tui::run(configfile) {
+        eprintln!("{}", error.display_chain());
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_: &Path) {
+    eprintln!("Minions is built without terminal frontend");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "tui")]
+fn run_tui_once<F>(build: F)
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    use error_chain::ChainedError;
+    if let Err(error) = minions::frontend::tui::run_once(build) {
+        eprintln!("{}", error.display_chain());
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui_once<F>(_: F)
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    eprintln!("Minions is built without terminal frontend");
+    std::process::exit(1);
+}
+
+/// Read candidates from stdin, print the chosen one to stdout
+fn run_dmenu(configfile: &Path, scope: &str, tui: bool) {
+    let stdin = std::io::stdin();
+    let lines = stdin.lock().lines()
+        .collect::<std::io::Result<Vec<String>>>()
+        .expect("Unable to read candidates from stdin");
+    let dmenu = Dmenu::new(lines, scope);
+    let config = Config::new(configfile);
+
+    let build = |clipboard: Box<dyn Clipboard>, notifier: Notifier| {
+        dmenu.build_controller(&config, clipboard, notifier)
+    };
+    if tui {
+        run_tui_once(build);
+    } else {
+        run_gui_once(build);
+    }
+
+    match dmenu.selected() {
+        Some(line) => println!("{}", line),
+        None => std::process::exit(1),
+    }
+}
+
+/// Run `query` or `run` subcommand, print result items as JSON
+fn run_cli(configfile: &Path, args: &clap::ArgMatches) {
+    use error_chain::ChainedError;
+
+    let config = Config::new(configfile);
+    let result = cli::Cli::from_config(&config).and_then(|cli| {
+        match args.subcommand() {
+            ("query", Some(sub_args)) => Ok(cli.query(sub_args.value_of("filter").unwrap_or(""))),
+            ("run", Some(sub_args)) => cli.run(sub_args.value_of("title").unwrap(),
+                                               sub_args.value_of("arg")),
+            _ => unreachable!(),
+        }
+    });
+    match result {
+        Ok(items) => println!("{}", cli::to_json(&items)),
+        Err(error) => {
+            eprintln!("{}", error.display_chain());
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Check config files (including included and drop-in ones), print problems and exit with failure if any
+fn run_check_config(configfile: &Path) {
+    let config = Config::new(configfile);
+    if config.errors().is_empty() {
+        let files: Vec<_> = config.files().iter().filter(|x| x.exists()).collect();
+        if files.is_empty() {
+            println!("{} does not exist, using default config", configfile.display());
+        }
+        for file in files {
+            println!("{}: OK", file.display());
+        }
+    } else {
+        for error in config.errors() {
+            eprintln!("{}", error);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Send request to the running instance via control socket, print the response
+fn run_ctl(configfile: &Path, args: &clap::ArgMatches) {
+    let request = match args.value_of("command").unwrap() {
+        "show" => ipc::Request::Show,
+        "hide" => ipc::Request::Hide,
+        "reload" => ipc::Request::Reload,
+        "status" => ipc::Request::Status,
+        "quicksend" => ipc::Request::Quicksend {
+            text: match args.value_of("text") {
+                Some(text) => text.into(),
+                None => {
+                    let mut text = String::new();
+                    std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+                        .expect("Unable to read text from stdin");
+                    text.trim_end_matches('\n').into()
+                },
+            },
+        },
+        _ => unreachable!(),
+    };
+
+    send_request(&Config::new(configfile), &request, true);
+}
+
+/// Run `plugin` subcommand to manage installed plugins, reload the running instance after changes
+fn run_plugin(configfile: &Path, args: &clap::ArgMatches) {
+    use error_chain::ChainedError;
+
+    let config = Config::new(configfile);
+    let manager = PluginManager::from_config(&config);
+    let (command, sub_args) = args.subcommand();
+    let target = sub_args.and_then(|x| x.value_of("target")).unwrap_or("");
+    let result = match command {
+        "list" => manager.list().map(|plugins| {
+            for plugin in plugins.iter() {
+                println!("{}\t{}\t{}", plugin.name,
+                         plugin.version.as_ref().map(|x| x.as_str()).unwrap_or("-"),
+                         if plugin.enabled { "enabled" } else { "disabled" });
+            }
+        }),
+        "install" => manager.install(Path::new(target), false).map(|name| println!("Installed {}", name)),
+        "update" => manager.install(Path::new(target), true).map(|name| println!("Updated {}", name)),
+        "remove" => manager.remove(target),
+        "enable" => manager.set_enabled(target, true),
+        "disable" => manager.set_enabled(target, false),
+        _ => unreachable!(),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error.display_chain());
+        std::process::exit(1);
+    }
+
+    if command != "list" {
+        let socket_path = config.get_filename(&["core", "control_socket"]).unwrap();
+        // the instance may not be running, which is fine
+        if !socket_path.as_os_str().is_empty() && ipc::send(&socket_path, &ipc::Request::Reload).is_ok() {
+            println!("Reloaded running instance");
+        }
+    }
+}
+
+/// Send request via control socket, exit on failure
+fn send_request(config: &Config, request: &ipc::Request, print_response: bool) {
+    use error_chain::ChainedError;
+
+    let socket_path = config.get_filename(&["core", "control_socket"]).unwrap();
+    if socket_path.as_os_str().is_empty() {
+        eprintln!("Control socket is disabled");
+        std::process::exit(1);
+    }
+    match ipc::send(&socket_path, request) {
+        Ok(response) => {
+            if print_response {
+                println!("{}", serde_json::to_string_pretty(&response).unwrap());
+            }
+            if !response.ok {
+                eprintln!("{}", response.error.unwrap_or_default());
+                std::process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("{}", error.display_chain());
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Run the GTK frontend, or forward the request to the running instance if any
+fn run_instance(configfile: &Path, request: Option<ipc::Request>) {
+    use error_chain::ChainedError;
+
+    let config = Config::new(configfile);
+    let lock_file = config.get_filename(&["core", "lock_file"]).unwrap();
+    match InstanceLock::try_acquire(&lock_file) {
+        Ok(Some(_lock)) => run_gui(configfile, request.as_ref()),
+        Ok(None) => {
+            log::info!("Minions is already running, forwarding request");
+            send_request(&config, &request.unwrap_or(ipc::Request::Show), false);
+        },
+        Err(error) => {
+            log::warn!("Unable to check running instance: {}", error.display_chain());
+            run_gui(configfile, request.as_ref());
+        },
+    }
+}
 
 fn main() {
     let mut logger = fern::Dispatch::new()
@@ -37,13 +272,92 @@ fn main() {
                                       .long("verbose")
                                       .multiple(true)
                                       .help("Increase logging verbosity, up to 2 times"))
+                        .arg(clap::Arg::with_name("tui")
+                                      .short("t")
+                                      .long("tui")
+                                      .help("Run in terminal instead of GTK window"))
+                        .arg(clap::Arg::with_name("dmenu")
+                                      .long("dmenu")
+                                      .help("Choose one line from stdin and print it to stdout, like dmenu"))
+                        .arg(clap::Arg::with_name("dmenu_scope")
+                                      .long("dmenu-scope")
+                                      .help("Statistics scope for --dmenu, use different scopes for different scripts")
+                                      .takes_value(true)
+                                      .default_value("default"))
+                        .arg(clap::Arg::with_name("quicksend")
+                                      .long("quicksend")
+                                      .help("Show window with given text to quicksend")
+                                      .takes_value(true)
+                                      .value_name("TEXT"))
+                        .arg(clap::Arg::with_name("reload")
+                                      .long("reload")
+                                      .help("Reload the running instance"))
+                        .subcommand(clap::SubCommand::with_name("query")
+                                    .about("Print items matching the filter text as JSON")
+                                    .arg(clap::Arg::with_name("filter")
+                                                  .help("Filter text, list all items if empty")
+                                                  .index(1)))
+                        .subcommand(clap::SubCommand::with_name("run")
+                                    .about("Run the item with given title and print results as JSON")
+                                    .arg(clap::Arg::with_name("title")
+                                                  .help("Title of the item")
+                                                  .required(true)
+                                                  .index(1))
+                                    .arg(clap::Arg::with_name("arg")
+                                                  .long("arg")
+                                                  .help("Text argument for the item")
+                                                  .takes_value(true)))
+                        .subcommand(clap::SubCommand::with_name("ctl")
+                                    .about("Control the running instance")
+                                    .arg(clap::Arg::with_name("command")
+                                                  .required(true)
+                                                  .possible_values(&["show", "hide", "quicksend", "reload", "status"])
+                                                  .index(1))
+                                    .arg(clap::Arg::with_name("text")
+                                                  .help("Text to quicksend, read from stdin if not given")
+                                                  .index(2)))
+                        .subcommand(clap::SubCommand::with_name("check-config")
+                                    .about("Check config file for syntax errors, unknown keys and wrong types"))
+                        .subcommand(clap::SubCommand::with_name("plugin")
+                                    .about("Manage installed plugins")
+                                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                                    .subcommand(clap::SubCommand::with_name("list")
+                                                .about("List installed plugins with versions"))
+                                    .subcommands(vec![
+                                        ("install", "Install plugin from local directory or tarball", "Path of the plugin"),
+                                        ("update", "Install plugin, replacing the installed one", "Path of the plugin"),
+                                        ("remove", "Remove installed plugin", "Name of the plugin"),
+                                        ("enable", "Enable installed plugin", "Name of the plugin"),
+                                        ("disable", "Disable installed plugin without removing it", "Name of the plugin"),
+                                    ].into_iter().map(|(name, about, help)| {
+                                        clap::SubCommand::with_name(name)
+                                            .about(about)
+                                            .arg(clap::Arg::with_name("target")
+                                                          .help(help)
+                                                          .required(true)
+                                                          .index(1))
+                                    })))
                         .get_matches();
 
     logger = match args.occurrences_of("verbose") {
+        // problems are printed by the command itself
+        0 if args.subcommand_name() == Some("check-config") => logger.level_for("minions", log::LevelFilter::Error),
+        // logs would mess up the terminal UI (or the JSON output)
+        0 if args.is_present("tui") || args.subcommand_name().is_some() => logger.level_for("minions", log::LevelFilter::Warn),
         0 => logger.level_for("minions", log::LevelFilter::Info),
         1 => logger.level_for("minions", log::LevelFilter::Debug),
         _ => logger.level_for("minions", log::LevelFilter::Trace),
     };
+    // the terminal UI owns the terminal, send logs to file unless stderr is redirected
+    let log_output: fern::Output = if args.is_present("tui") && nix::unistd::isatty(2).unwrap_or(false) {
+        let log_file = dirs::home_dir().unwrap().join(".minions/tui.log");
+        match std::fs::create_dir_all(log_file.parent().unwrap()).and_then(|_| fern::log_file(&log_file)) {
+            Ok(file) => file.into(),
+            Err(_) => (Box::new(std::io::sink()) as Box<dyn std::io::Write + Send>).into(),
+        }
+    } else {
+        std::io::stderr().into()
+    };
     let logger_colors = fern::colors::ColoredLevelConfig::default();
     logger.format(move |out, message, record| {
                out.finish(format_args!("{}[{}][{}] {}",
@@ -52,7 +366,7 @@ fn main() {
                                        record.target(),
                                        message))
            })
-          .chain(std::io::stderr())
+          .chain(log_output)
           .apply()
           .expect("Unable to setup logging");
 
@@ -61,7 +375,26 @@ fn main() {
         None => dirs::home_dir().unwrap().join(".minions/config.toml"),
     };
 
-    gtk::init().expect("Failed to initialize GTK");
-    let _ = MinionsApp::new(&configfile);
-    gtk::main();
+    if let ("ctl", Some(sub_args)) = args.subcommand() {
+        run_ctl(&configfile, sub_args);
+    } else if let ("plugin", Some(sub_args)) = args.subcommand() {
+        run_plugin(&configfile, sub_args);
+    } else if args.subcommand_name() == Some("check-config") {
+        run_check_config(&configfile);
+    } else if args.subcommand_name().is_some() {
+        run_cli(&configfile, &args);
+    } else if args.is_present("dmenu") {
+        run_dmenu(&configfile, args.value_of("dmenu_scope").unwrap(), args.is_present("tui"));
+    } else if args.is_present("tui") {
+        run_tui(&configfile);
+    } else {
+        let request = if let Some(text) = args.value_of("quicksend") {
+            Some(ipc::Request::Quicksend { text: text.into() })
+        } else if args.is_present("reload") {
+            Some(ipc::Request::Reload)
+        } else {
+            None
+        };
+        run_instance(&configfile, request);
+    }
 }
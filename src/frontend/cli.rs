@@ -0,0 +1,179 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-26
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate serde_json;
+
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use crate::mcore::action::ActionResult;
+use crate::mcore::context::Context;
+use crate::mcore::item::Item;
+use crate::mcore::matcher::Matcher;
+use crate::mcore::config::Config;
+use crate::mcore::clipboard::NullClipboard;
+use crate::mcore::errors::*;
+
+/// Item in JSON output
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ItemOutput {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub badge: Option<String>,
+    pub data: Option<String>,
+    /// Runnable without argument
+    pub selectable: bool,
+    /// Runnable with argument (`--arg`)
+    pub selectable_with_text: bool,
+}
+
+impl ItemOutput {
+    fn new(ctx: &Context, item: &Item) -> ItemOutput {
+        ItemOutput {
+            title: item.title.clone(),
+            subtitle: item.subtitle.clone(),
+            badge: item.badge.clone(),
+            data: item.data.clone(),
+            selectable: ctx.selectable(item),
+            selectable_with_text: ctx.selectable_with_text(item),
+        }
+    }
+}
+
+/// Non-interactive frontend, for scripting and testing
+pub struct Cli {
+    ctx: Context,
+    matcher: Matcher,
+}
+
+impl Cli {
+
+    pub fn new(ctx: Context, matcher: Matcher) -> Cli {
+        Cli { ctx, matcher }
+    }
+
+    pub fn from_config(config: &Config) -> Result<Cli> {
+        let matcher = Matcher::from_config(config, None).chain_err(|| "Unable to open statistic file")?;
+        Ok(Cli::new(Context::new(config, Box::new(NullClipboard{})), matcher))
+    }
+
+    /// Filter and sort all items, like typing `filter` in the window
+    pub fn query(&self, filter: &str) -> Vec<ItemOutput> {
+        let items = if filter.is_empty() {
+            self.ctx.list_items.clone()
+        } else {
+            self.matcher.sort(filter, None, &self.ctx.list_items).into_iter().map(|(item, _)| item).collect()
+        };
+        items.iter().map(|item| ItemOutput::new(&self.ctx, &item)).collect()
+    }
+
+    /// Run item with given title, optionally with argument
+    /// It's run by the context like in the window, with the timeout in config
+    pub fn run(&self, title: &str, arg: Option<&str>) -> Result<Vec<ItemOutput>> {
+        let item: Rc<Item> = match self.ctx.list_items.iter().find(|x| x.title == title) {
+            Some(item) => item.clone(),
+            None => bail!("No item titled {:?}", title),
+        };
+        let action = match item.action {
+            Some(ref action) => action.clone(),
+            None => bail!("Item {:?} has no action", title),
+        };
+
+        // streamed batches, and the remaining items once finished
+        let (sender, receiver) = mpsc::channel::<(ActionResult, bool)>();
+        let batch_sender = sender.clone();
+        let on_batch = move |items: Vec<Item>| { let _ = batch_sender.send((Ok(items), false)); };
+        let callback = move |items: ActionResult| { let _ = sender.send((items, true)); };
+        match arg {
+            Some(arg) if action.runnable_arg() => self.ctx.async_select_with_text(&item, arg, on_batch, callback),
+            Some(_) => bail!("Item {:?} does not accept argument", title),
+            None if action.runnable_bare() => self.ctx.async_select(&item, on_batch, callback),
+            None => bail!("Item {:?} requires argument", title),
+        };
+
+        let mut result = Vec::new();
+        loop {
+            let (items, finished) = receiver.recv()
+                .chain_err(|| format!("Failed running {:?}: no result", title))?;
+            result.extend(items.chain_err(|| format!("Failed running {:?}", title))?);
+            if finished {
+                break;
+            }
+        }
+        result.sort_by_key(|x| x.priority);
+        Ok(result.iter().map(|item| ItemOutput::new(&self.ctx, &item)).collect())
+    }
+}
+
+/// Serialize output items as pretty JSON
+pub fn to_json(items: &[ItemOutput]) -> String {
+    serde_json::to_string_pretty(items).unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use crate::mcore::action::Action;
+    use crate::mcore::lrudb::LruDB;
+
+    struct EchoAction {}
+
+    impl Action for EchoAction {
+        fn runnable_arg(&self) -> bool { true }
+        fn run_arg(&self, text: &str) -> ActionResult {
+            Ok(vec![Item { title: format!("echo {}", text), .. Item::default() }])
+        }
+    }
+
+    struct SlowAction {}
+
+    impl Action for SlowAction {
+        fn runnable_bare(&self) -> bool { true }
+        fn run_bare(&self) -> ActionResult {
+            std::thread::sleep(Duration::from_secs(1));
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn cli_query_run_test() {
+        let statistic_file = std::env::temp_dir().join(
+            format!("minions-cli-test-{}.dat", std::process::id()));
+        let items = vec![
+            Item { title: "Echo".into(), action: Some(Arc::new(EchoAction{})), .. Item::default() },
+            Item { title: "Nothing".into(), .. Item::default() },
+            Item { title: "Slow".into(), action: Some(Arc::new(SlowAction{})), .. Item::default() },
+        ];
+        let mut ctx = Context::with_items(items, LruDB::new(None).unwrap(), 10, Box::new(NullClipboard{}));
+        ctx.set_timeouts(Some(Duration::from_millis(50)), None);
+        let cli = Cli::new(ctx, Matcher::new(&statistic_file, "test-salt", None).unwrap());
+
+        assert_eq!(cli.query("").len(), 3);
+        let result = cli.query("ech");
+        assert_eq!(result[0].title, "Echo");
+        assert!(!result[0].selectable);
+        assert!(result[0].selectable_with_text);
+
+        let result = cli.run("Echo", Some("hello")).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].title, "echo hello");
+        assert!(to_json(&result).contains("\"echo hello\""));
+
+        assert!(cli.run("Echo", None).is_err());
+        assert!(cli.run("Nothing", None).is_err());
+        assert!(cli.run("Missing", None).is_err());
+
+        let start = Instant::now();
+        assert!(cli.run("Slow", None).is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let _ = std::fs::remove_file(&statistic_file);
+    }
+}
@@ -0,0 +1,34 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-20
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-01-20
+*/
+
+use crate::frontend::gdk;
+use crate::frontend::gtk;
+
+use crate::mcore::clipboard::Clipboard;
+use crate::mcore::errors::*;
+
+/// System clipboard via GTK
+pub struct GtkClipboard {}
+
+impl Clipboard for GtkClipboard {
+    /// Try PRIMARY (selected text) first, then CLIPBOARD
+    fn get_text(&self) -> Option<String> {
+        for clipboard in vec!["PRIMARY", "CLIPBOARD"] {
+            let clipboard = gtk::Clipboard::get(&gdk::Atom::intern(&clipboard));
+            if let Some(text) = clipboard.wait_for_text() {
+                return Some(text.as_str().into());
+            }
+        }
+        None
+    }
+
+    fn set_text(&self, text: &str) -> Result<()> {
+        let clipboard = gtk::Clipboard::get(&gdk::Atom::intern("CLIPBOARD"));
+        clipboard.set_text(text);
+        Ok(())
+    }
+}
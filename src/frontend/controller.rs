@@ -0,0 +1,1559 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-24
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate lru_cache;
+
+use std;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::rc::Rc;
+
+use crate::frontend::view::{Key, ViewModel, EntryView, ItemView};
+use crate::frontend::ipc::{Request, Response, StatusInfo};
+use crate::mcore::context::Context;
+use crate::mcore::action::{Action, ActionResult, BuiltinAction, CancelToken};
+use crate::mcore::item::Item;
+use crate::mcore::matcher::Matcher;
+use crate::mcore::config::Config;
+use crate::mcore::clipboard::Clipboard;
+use crate::mcore::errors::Error;
+use crate::actions::Provider;
+use crate::actions::utils::typing;
+
+use self::lru_cache::LruCache;
+use error_chain::ChainedError;
+
+/// Result receiver of a running action, with the token to cancel it
+#[derive(Clone)]
+struct Pending {
+    receiver: Rc<mpsc::Receiver<ActionResult>>,
+    token: CancelToken,
+    /// Items streamed before the result, if supported
+    batches: Option<Rc<mpsc::Receiver<Vec<Item>>>>,
+}
+
+impl Pending {
+    fn take_batches(&self) -> Vec<Item> {
+        match self.batches {
+            Some(ref batches) => batches.try_iter().flatten().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Status {
+    Initial,
+    Running(Pending),
+    Error(Rc<Error>), // Rc is for Clone
+    Default,
+    Filtering {
+        selected_idx: i32,
+        filter_text: String,
+        filtered_items: Vec<Rc<Item>>,
+        highlights: Vec<Vec<usize>>, // matched chars of filtered items, empty if not filtered
+        timestamp: std::time::Instant,
+    },
+    Entering {
+        item: Rc<Item>, // entering text for item
+        suggestions: Vec<Rc<Item>>,
+        selected_idx: i32, // selected index of suggestions
+        pending: Option<Pending>, // running suggestion
+    },
+}
+
+/// Events sent from worker threads, should be processed in UI thread
+/// by `Controller::process_event`
+#[derive(Clone, Debug)]
+pub enum AppEvent {
+    /// Selected action completed
+    RunningDone,
+    /// Selected action streamed some items
+    RunningBatch,
+    /// Realtime action completed, with the entry text it runs with
+    RealtimeDone(String),
+    /// Entry text is not changed for a while (`realtime_debounce`) since then
+    RealtimeDebounced(String),
+}
+
+/// Called from worker threads to deliver `AppEvent` to the UI thread
+pub type Notifier = Arc<dyn Fn(AppEvent) + Send + Sync>;
+
+/// Single worker thread delaying entry texts, which notifies `RealtimeDebounced`
+/// with the latest text once no newer one is pushed within the delay
+/// The thread exits once the debouncer is dropped
+struct Debouncer {
+    sender: mpsc::Sender<String>,
+    delay: Arc<Mutex<std::time::Duration>>,
+}
+
+impl Debouncer {
+    fn new(delay: std::time::Duration, notifier: Notifier) -> Debouncer {
+        let (sender, receiver) = mpsc::channel::<String>();
+        let delay = Arc::new(Mutex::new(delay));
+        let thread_delay = delay.clone();
+        std::thread::spawn(move || {
+            while let Ok(mut text) = receiver.recv() {
+                loop {
+                    let delay = *thread_delay.lock().unwrap();
+                    match receiver.recv_timeout(delay) {
+                        Ok(newer) => text = newer,
+                        Err(mpsc::RecvTimeoutError::Timeout) => {
+                            notifier(AppEvent::RealtimeDebounced(text));
+                            break;
+                        },
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+            }
+        });
+        Debouncer { sender: sender, delay: delay }
+    }
+
+    /// Update the delay, which applies from the next pushed text
+    fn set_delay(&self, delay: std::time::Duration) {
+        *self.delay.lock().unwrap() = delay;
+    }
+
+    fn push(&self, text: String) {
+        if let Err(error) = self.sender.send(text) {
+            warn!("Unable to send to debouncer: {}", error);
+        }
+    }
+}
+
+/// The UI-agnostic state machine of the app
+///
+/// Frontends feed it with keys, entry text and `AppEvent`s,
+/// and render the `ViewModel` returned by `take_update`
+pub struct Controller {
+    ctx: Context,
+
+    status: Status,
+    filter_timeout: std::time::Duration,
+    matcher: Matcher,
+    notifier: Notifier,
+
+    /// Running action which is still streaming items to the list
+    streaming: Option<Pending>,
+
+    visible: bool,
+    entry_text: String,
+    updated: bool,
+
+    /// None if realtime actions run without delay
+    realtime_debouncer: Option<Debouncer>,
+    /// Recent realtime results of the action being entered, by entry text
+    realtime_cache: Option<(Arc<dyn Action + Sync + Send>, LruCache<String, Vec<Rc<Item>>>)>,
+    realtime_cache_size: usize,
+
+    /// Error of loading config file, shown in the initial window
+    config_error: Option<Rc<Error>>,
+    /// Reloading is requested, which is done by the frontend (see `take_reload_request`)
+    reload_requested: bool,
+}
+
+
+impl Controller {
+
+    fn update_ui(&mut self) {
+        trace!("update ui");
+        if let Status::Default = self.status {
+            if self.ctx.list_items.len() == 0 {
+                debug!("No more listing items!");
+                self.visible = false;
+            }
+        }
+        self.updated = true;
+    }
+
+    /// Views of items, highlighting matched chars in `highlights` (by the same index) if any
+    fn item_views(&self, items: &[Rc<Item>], highlights: &[Vec<usize>]) -> Vec<ItemView> {
+        items.iter().enumerate().map(|(idx, item)| ItemView {
+            item: item.clone(),
+            selectable: self.ctx.selectable(&item),
+            selectable_with_text: self.ctx.selectable_with_text(&item),
+            highlights: highlights.get(idx).cloned().unwrap_or_default(),
+        }).collect()
+    }
+
+    /// Current view model
+    pub fn view_model(&self) -> ViewModel {
+        let mut vm = ViewModel {
+            visible: self.visible,
+            spinning: false,
+            entry: EntryView::Item(None),
+            filter_text: String::new(),
+            action: None,
+            reference: None,
+            error: None,
+            items: Vec::new(),
+            highlight: -1,
+        };
+        match self.status {
+            Status::Initial => {
+                vm.error = self.config_error.clone();
+            },
+            Status::Running(_) => {
+                vm.spinning = true;
+            },
+            Status::Error(ref error) => {
+                vm.error = Some(error.clone());
+            },
+            Status::Default => {
+                vm.reference = self.ctx.reference.clone();
+                vm.items = self.item_views(&self.ctx.list_items, &[]);
+            },
+            Status::Filtering {
+                selected_idx,
+                ref filter_text,
+                ref filtered_items,
+                ref highlights,
+                ..
+            } => {
+                if selected_idx >= 0 {
+                    vm.entry = EntryView::Item(Some(filtered_items[selected_idx as usize].clone()));
+                }
+                vm.filter_text = filter_text.clone();
+                vm.reference = self.ctx.reference.clone();
+                vm.items = self.item_views(filtered_items, highlights);
+                vm.highlight = selected_idx;
+            },
+            Status::Entering {
+                ref item,
+                ref suggestions,
+                selected_idx,
+                pending: _
+            } => {
+                vm.entry = EntryView::Editable;
+                vm.action = Some(item.clone());
+                vm.items = self.item_views(suggestions, &[]);
+                vm.highlight = selected_idx;
+            },
+        }
+        if self.streaming.is_some() {
+            vm.spinning = true;
+        }
+        vm
+    }
+
+    /// Return the view model if it's updated since last call
+    pub fn take_update(&mut self) -> Option<ViewModel> {
+        if self.updated {
+            self.updated = false;
+            Some(self.view_model())
+        } else {
+            None
+        }
+    }
+
+    /// Cancel running action (or suggestion), if any
+    fn cancel_pending(&self) {
+        match self.status {
+            Status::Running(ref pending) | Status::Entering{pending: Some(ref pending), ..} => {
+                debug!("Cancelling running action");
+                pending.token.cancel();
+            },
+            _ => {},
+        }
+    }
+
+    fn cancel_streaming(&mut self) {
+        if let Some(streaming) = self.streaming.take() {
+            debug!("Cancelling streaming action");
+            streaming.token.cancel();
+        }
+    }
+
+    fn process_keyevent_escape(&mut self) {
+        trace!("Processing keyevent Escape");
+        self.cancel_pending();
+        self.status = match self.status {
+            Status::Initial => {
+                debug!("Quit!");
+                self.visible = false;
+                Status::Initial
+            },
+            Status::Default => {
+                self.cancel_streaming();
+                self.ctx.reset();
+                Status::Initial
+            },
+            _ => Status::Default,
+        };
+        self.update_ui();
+    }
+
+    fn process_keyevent_move(&mut self, delta: i32) {
+        trace!("Processing keyevent Move: {}", delta);
+        self.status = match self.status.clone() {
+            Status::Default | Status::Initial => {
+                Status::Filtering {
+                    selected_idx: 0,
+                    filter_text: String::new(),
+                    filtered_items: self.ctx.list_items.clone(),
+                    highlights: Vec::new(),
+                    timestamp: std::time::Instant::now(),
+                }
+            },
+            Status::Filtering {
+                selected_idx,
+                filter_text,
+                filtered_items,
+                highlights,
+                timestamp,
+            } => {
+                let mut new_idx = selected_idx + delta;
+                if filtered_items.len() == 0 {
+                    new_idx = -1;
+                } else {
+                    if new_idx >= filtered_items.len() as i32 {
+                        new_idx = filtered_items.len() as i32 - 1;
+                    }
+                    if new_idx < 0 {
+                        new_idx = 0;
+                    }
+                }
+                Status::Filtering {
+                    selected_idx: new_idx,
+                    filter_text: filter_text,
+                    filtered_items: filtered_items,
+                    highlights: highlights,
+                    timestamp: timestamp,
+                }
+            },
+            Status::Entering {
+                item,
+                suggestions,
+                selected_idx,
+                pending,
+            } => {
+                if let Some(pending) = pending {
+                    pending.token.cancel();
+                }
+                let mut new_idx = selected_idx + delta;
+                if new_idx >= suggestions.len() as i32 {
+                    new_idx = suggestions.len() as i32 - 1;
+                }
+                if new_idx < 0 {
+                    new_idx = -1;
+                }
+                Status::Entering {
+                    item: item,
+                    suggestions: suggestions,
+                    selected_idx: new_idx,
+                    pending: None,
+                }
+            },
+            status @ _ => status,
+        };
+        self.update_ui();
+    }
+
+    fn process_keyevent_tab(&mut self) {
+        trace!("Processing keyevent Tab");
+        self.status = match self.status.clone() {
+            Status::Filtering {
+                selected_idx,
+                ref filter_text,
+                ref filtered_items,
+                ..
+            } => {
+                if selected_idx < 0 || self.ctx.reference.is_some() {
+                    debug!("No item to send or not sendable");
+                    self.status.clone()
+                } else {
+                    let item = &filtered_items[selected_idx as usize];
+                    let reference = self.ctx.reference.as_ref().map(|x| x.as_str());
+                    if let Err(error) = self.matcher.record(Some(&filter_text), reference, &item) {
+                        warn!("Unable to record hit: {}", error);
+                    }
+                    self.cancel_streaming();
+                    if let Err(error) = self.ctx.quicksend(item) {
+                        debug!("Unable to quicksend item: {}", error.display_chain());
+                        Status::Error(Rc::new(error))
+                    } else {
+                        Status::Default
+                    }
+                }
+            },
+            status @ _ => status,
+        };
+        self.update_ui();
+    }
+
+    fn process_keyevent_char(&mut self, ch: char) {
+        trace!("Processing keyevent Char: {}", ch);
+
+        let newfilter = match self.status {
+            Status::Initial | Status::Default => {
+                let mut text = String::new();
+                text.push(ch);
+                Some(text)
+            },
+            Status::Filtering {
+                ref filter_text,
+                ref timestamp,
+                ..
+            } => {
+                let mut text = String::new();
+                if (self.filter_timeout.as_secs() == 0 && self.filter_timeout.subsec_nanos() == 0)
+                    || timestamp.elapsed() < self.filter_timeout {
+                    text = filter_text.clone();
+                }
+                text.push(ch);
+                Some(text)
+            },
+            _ => None,
+        };
+
+        if let Some(newfilter) = newfilter {
+            let (filtered_items, highlights) = self.matcher.sort(
+                &newfilter, self.ctx.reference.as_ref().map(|x| x.as_str()), &self.ctx.list_items)
+                .into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+            let selected_idx = if filtered_items.len() == 0 { -1 } else { 0 };
+
+            self.status = Status::Filtering {
+                selected_idx: selected_idx,
+                filter_text: newfilter,
+                filtered_items: filtered_items,
+                highlights: highlights,
+                timestamp: std::time::Instant::now(),
+            };
+            self.update_ui();
+        }
+    }
+
+    fn process_keyevent_space(&mut self) {
+        trace!("Processing keyevent Space");
+        let mut should_update_ui = false;
+        self.status = match self.status.clone() {
+            Status::Filtering {
+                selected_idx,
+                ref filter_text,
+                ref filtered_items,
+                ..
+            } => {
+                if selected_idx < 0 {
+                    debug!("No item to select");
+                    self.status.clone()
+                } else {
+                    let item = &filtered_items[selected_idx as usize];
+                    let reference = self.ctx.reference.as_ref().map(|x| x.as_str());
+                    if let Err(error) = self.matcher.record(Some(&filter_text), reference, &item) {
+                        warn!("Unable to record hit: {}", error);
+                    }
+                    self.ctx.filter_text = Some(filter_text.clone());
+                    if self.ctx.selectable_with_text(item) {
+                        should_update_ui = true;
+                        Status::Entering{
+                            item: item.clone(),
+                            suggestions: self.initial_suggestions(item),
+                            selected_idx: -1,
+                            pending: None,
+                        }
+                    } else {
+                        debug!("Item not selectable with text");
+                        self.status.clone()
+                    }
+                }
+            },
+            status @ _ => status,
+        };
+
+        if should_update_ui {
+            self.update_ui();
+        }
+    }
+
+    /// Set current text of the (editable) entry
+    pub fn set_entry_text(&mut self, text: &str) {
+        self.entry_text = text.into();
+    }
+
+    /// Realtime cache of item's action, which is reset if another action is entered
+    fn realtime_cache_of(&mut self, item: &Item) -> Option<&mut LruCache<String, Vec<Rc<Item>>>> {
+        let action = item.action.as_ref()?;
+        if self.realtime_cache_size == 0 {
+            return None;
+        }
+        let reset = match self.realtime_cache {
+            Some((ref cached, _)) => !Arc::ptr_eq(cached, action),
+            None => true,
+        };
+        if reset {
+            self.realtime_cache = Some((action.clone(), LruCache::new(self.realtime_cache_size)));
+        }
+        self.realtime_cache.as_mut().map(|x| &mut x.1)
+    }
+
+    /// Suggestions of item when entering starts, i.e. its arg history
+    fn initial_suggestions(&self, item: &Item) -> Vec<Rc<Item>> {
+        self.ctx.suggest_arg(item)
+            .unwrap_or(Vec::new())
+            .into_iter().map(|x| Rc::new(x)).collect()
+    }
+
+    /// Should be called by frontend when the (editable) entry text is changed
+    pub fn process_entry_text_changed(&mut self, text: &str) {
+        self.set_entry_text(text);
+
+        if let Status::Entering{item, suggestions, selected_idx, pending} = self.status.clone() {
+            if !self.ctx.runnable_with_text_realtime(&item) {
+                return;
+            }
+            let entry_text = self.entry_text.clone();
+            trace!("Entry text changed: {}", &entry_text);
+
+            // result of previous text is useless now
+            if let Some(pending) = pending {
+                pending.token.cancel();
+            }
+
+            let cached = self.realtime_cache_of(&item)
+                .and_then(|cache| cache.get_mut(&entry_text))
+                .map(|x| x.clone());
+            let has_cache = cached.is_some();
+            self.status = match cached {
+                Some(cached) => {
+                    trace!("Using cached realtime result");
+                    Status::Entering {
+                        item: item,
+                        suggestions: cached,
+                        selected_idx: -1,
+                        pending: None,
+                    }
+                },
+                // results of previous text are outdated
+                None if entry_text.is_empty() => Status::Entering {
+                    suggestions: self.initial_suggestions(&item),
+                    item: item,
+                    selected_idx: -1,
+                    pending: None,
+                },
+                None => Status::Entering {
+                    item: item,
+                    suggestions: suggestions,
+                    selected_idx: selected_idx,
+                    pending: None,
+                },
+            };
+            self.update_ui();
+
+            if has_cache || entry_text.is_empty() {
+                return;
+            }
+            match self.realtime_debouncer {
+                Some(ref debouncer) => debouncer.push(entry_text),
+                None => self.run_text_realtime(),
+            }
+        }
+    }
+
+    fn process_text_realtime_debounced(&mut self, text: &str) {
+        if text == self.entry_text {
+            self.run_text_realtime();
+        } else {
+            trace!("Entry text changed during debounce, skip");
+        }
+    }
+
+    /// Run realtime action with current entry text, if no one is running
+    fn run_text_realtime(&mut self) {
+        if let Status::Entering{item, suggestions, pending: None, ..} = self.status.clone() {
+            let (send_ch, recv_ch) = mpsc::channel::<ActionResult>();
+            let entry_text = self.entry_text.clone();
+            let notifier = self.notifier.clone();
+            let token = self.ctx.async_run_with_text_realtime(&item, &self.entry_text, move |res: ActionResult| {
+                if let Err(error) = send_ch.send(res) {
+                    warn!("Unable to send to channel: {}", error);
+                } else {
+                    notifier(AppEvent::RealtimeDone(entry_text));
+                }
+            });
+            self.status = Status::Entering {
+                item: item,
+                suggestions: suggestions,
+                selected_idx: -1,
+                pending: Some(Pending { receiver: Rc::new(recv_ch), token: token, batches: None }),
+            };
+        }
+    }
+
+    fn process_running_text_realtime_callback(&mut self, text: &str) {
+        if text != self.entry_text {
+            debug!("Discard realtime result of outdated text");
+            return;
+        }
+        if let Status::Entering{item, suggestions, pending: Some(pending), ..} = self.status.clone() {
+            if let Ok(res) = pending.receiver.try_recv() {
+                trace!("Received realtime text result on callback");
+                self.status = match res {
+                    Ok(res) => {
+                        let res: Vec<Rc<Item>> = res.into_iter().map(|x| Rc::new(x)).collect();
+                        if let Some(cache) = self.realtime_cache_of(&item) {
+                            cache.insert(text.into(), res.clone());
+                        }
+                        Status::Entering {
+                            item: item,
+                            suggestions: res,
+                            selected_idx: -1,
+                            pending: None
+                        }
+                    },
+                    Err(error) => {
+                        warn!("Error running realtime text: {}", error.display_chain());
+                        Status::Entering {
+                            item: item,
+                            suggestions: suggestions,
+                            selected_idx: -1,
+                            pending: None,
+                        }
+                    }
+                };
+                self.update_ui();
+            } else {
+                debug!("Unable to receive realtime text result from channel");
+            }
+        } else {
+            debug!("Invalid status on realtime text callback");
+        }
+
+    }
+
+    /// Re-filter items after the list is changed, keep the selected item if possible
+    fn refilter(&mut self) {
+        if let Status::Filtering{selected_idx, filter_text, filtered_items, timestamp, ..} = self.status.clone() {
+            let (new_items, highlights) = if filter_text.is_empty() {
+                (self.ctx.list_items.clone(), Vec::new())
+            } else {
+                self.matcher.sort(&filter_text, self.ctx.reference.as_ref().map(|x| x.as_str()),
+                                  &self.ctx.list_items)
+                    .into_iter().unzip()
+            };
+            let mut new_idx = if new_items.is_empty() { -1 } else { 0 };
+            if selected_idx >= 0 {
+                let selected = &filtered_items[selected_idx as usize];
+                if let Some(idx) = new_items.iter().position(|x| Rc::ptr_eq(x, selected)) {
+                    new_idx = idx as i32;
+                }
+            }
+            self.status = Status::Filtering {
+                selected_idx: new_idx,
+                filter_text: filter_text,
+                filtered_items: new_items,
+                highlights: highlights,
+                timestamp: timestamp,
+            };
+        }
+    }
+
+    fn process_running_batch(&mut self) {
+        if let Status::Running(pending) = self.status.clone() {
+            let items = pending.take_batches();
+            if items.is_empty() {
+                return;
+            }
+            // show the first batch, and keep receiving the rest
+            trace!("Received first batch of {} items", items.len());
+            self.ctx.async_select_callback(items);
+            self.streaming = Some(pending);
+            self.status = Status::Default;
+        } else if let Some(streaming) = self.streaming.clone() {
+            let items = streaming.take_batches();
+            if items.is_empty() {
+                return;
+            }
+            trace!("Received batch of {} items", items.len());
+            self.ctx.append_items(items);
+            self.refilter();
+        } else {
+            debug!("No running action for batch");
+            return;
+        }
+        self.update_ui();
+    }
+
+    fn process_running_callback(&mut self) {
+        if let Status::Running(pending) = self.status.clone() {
+            if let Ok(res) = pending.receiver.try_recv() {
+                trace!("Received result on callback");
+                self.status = match res {
+                    Ok(res) => {
+                        let mut items = pending.take_batches();
+                        items.extend(res);
+                        self.ctx.async_select_callback(items);
+                        Status::Default
+                    },
+                    Err(error) => {
+                        debug!("Error from channel: {}", error.display_chain());
+                        Status::Error(Rc::new(error))
+                    }
+                };
+                self.update_ui();
+            } else {
+                debug!("Unable to receive from channel");
+            }
+        } else if let Some(streaming) = self.streaming.clone() {
+            if let Ok(res) = streaming.receiver.try_recv() {
+                trace!("Received result of streaming action on callback");
+                self.streaming = None;
+                match res {
+                    Ok(res) => {
+                        let mut items = streaming.take_batches();
+                        items.extend(res);
+                        self.ctx.append_items(items);
+                        self.refilter();
+                    },
+                    Err(error) => {
+                        debug!("Error from channel: {}", error.display_chain());
+                        self.status = Status::Error(Rc::new(error));
+                    }
+                }
+                self.update_ui();
+            } else {
+                debug!("Unable to receive from channel");
+            }
+        } else {
+            debug!("No action result");
+        }
+    }
+
+    /// Perform builtin action in place, return the new status
+    fn run_builtin(&mut self, builtin: BuiltinAction) -> Status {
+        debug!("Running builtin action: {:?}", builtin);
+        let result = match builtin {
+            BuiltinAction::Copy(text) => {
+                self.ctx.copy_text_to_clipboard(&text).map(|_| {
+                    info!("Text copied");
+                    self.visible = false;
+                    self.status.clone()
+                })
+            },
+            BuiltinAction::Quicksend(text) => {
+                self.cancel_streaming();
+                self.ctx.quicksend(&Item { title: text, .. Item::default() })
+                    .map(|_| Status::Default)
+            },
+            BuiltinAction::Type(text) => {
+                // hidden first, the text is typed after the focus returns to the previous window
+                self.visible = false;
+                typing::type_text(&text)
+                    .map(|_| self.status.clone())
+                    .map_err(|e| Error::with_chain(e, "Failed to type text"))
+            },
+            BuiltinAction::Reload => {
+                self.reload_requested = true;
+                Ok(Status::Default)
+            },
+        };
+        match result {
+            Ok(status) => status,
+            Err(error) => {
+                debug!("Error running builtin action: {}", error.display_chain());
+                Status::Error(Rc::new(error))
+            },
+        }
+    }
+
+    /// Start running item (with text, if given) in background, return the new status
+    fn start_running(&mut self, item: &Item, text: Option<&str>) -> Status {
+        if text.is_none() {
+            if let Some(builtin) = item.action.as_ref().and_then(|x| x.builtin()) {
+                return self.run_builtin(builtin);
+            }
+        }
+
+        // the list would be replaced by the result
+        self.cancel_streaming();
+
+        let (send_ch, recv_ch) = mpsc::channel::<ActionResult>();
+        let (batch_send_ch, batch_recv_ch) = mpsc::channel::<Vec<Item>>();
+        let notifier = self.notifier.clone();
+        let batch_notifier = self.notifier.clone();
+        let on_batch = move |items: Vec<Item>| {
+            if batch_send_ch.send(items).is_ok() {
+                batch_notifier(AppEvent::RunningBatch);
+            }
+        };
+        let on_done = move |res: ActionResult| {
+            if let Err(error) = send_ch.send(res) {
+                debug!("Unable to send to channel: {}", error);
+            } else {
+                notifier(AppEvent::RunningDone);
+            }
+        };
+        let token = match text {
+            Some(text) => self.ctx.async_select_with_text(item, text, on_batch, on_done),
+            None => self.ctx.async_select(item, on_batch, on_done),
+        };
+        Status::Running(Pending {
+            receiver: Rc::new(recv_ch),
+            token: token,
+            batches: Some(Rc::new(batch_recv_ch)),
+        })
+    }
+
+    fn process_keyevent_enter(&mut self) {
+        trace!("Processing keyevent Enter");
+        self.status = match self.status.clone() {
+            status @ Status::Initial | status @ Status::Default => status,
+            Status::Filtering {
+                selected_idx,
+                ref filter_text,
+                ref filtered_items,
+                ..
+            } => {
+                if selected_idx < 0 {
+                    debug!("No item to select");
+                    self.status.clone()
+                } else {
+                    let item = &filtered_items[selected_idx as usize];
+                    let reference = self.ctx.reference.as_ref().map(|x| x.as_str());
+                    if let Err(error) = self.matcher.record(Some(&filter_text), reference, &item) {
+                        debug!("Unable to record hit: {}", error);
+                    }
+                    self.ctx.filter_text = Some(filter_text.clone());
+
+                    if self.ctx.selectable(item) {
+                        self.start_running(item, None)
+                    } else if self.ctx.selectable_with_text(&item) {
+                        Status::Entering{
+                            item: item.clone(),
+                            suggestions: self.initial_suggestions(item),
+                            selected_idx: -1,
+                            pending: None,
+                        }
+                    } else {
+                        debug!("Item not selectable with or without text");
+                        self.status.clone()
+                    }
+                }
+            },
+            Status::Entering{item, suggestions, selected_idx, pending} => {
+                if let Some(pending) = pending {
+                    pending.token.cancel();
+                }
+                if selected_idx < 0 {
+                    let text = self.entry_text.clone();
+                    self.start_running(&item, Some(&text))
+                } else {
+                    let item = &suggestions[selected_idx as usize];
+                    if self.ctx.selectable(&item) {
+                        self.start_running(item, None)
+                    } else {
+                        debug!("Item not selectable with nothing");
+                        self.status.clone()
+                    }
+                }
+
+            },
+            status @ _ => status,
+        };
+        self.update_ui();
+    }
+
+    fn process_keyevent_copy(&mut self) {
+        trace!("Process keyevent copy");
+        self.status = match self.status.clone() {
+            Status::Filtering {
+                selected_idx,
+                filtered_items,
+                ..
+            } | Status::Entering{
+                selected_idx,
+                suggestions: filtered_items,
+                ..
+            } => {
+                if selected_idx < 0 {
+                    debug!("No item to copy");
+                } else {
+                    let item = &filtered_items[selected_idx as usize];
+                    if let Err(error) = self.ctx.copy_content_to_clipboard(item) {
+                        warn!("Unable to copy item: {}", error.display_chain());
+                    } else {
+                        info!("Item copied");
+                    }
+                }
+                self.status.clone()
+            },
+            status @ _ => { status },
+        };
+        self.visible = false;
+        self.update_ui();
+    }
+
+    /// Process key pressed, return true if the key is consumed
+    /// (should not be further processed by the frontend, e.g. inserted into the entry)
+    pub fn process_key(&mut self, key: Key) -> bool {
+        trace!("Key pressed: {:?}", key);
+        match key {
+            Key::Enter => { self.process_keyevent_enter(); true },
+            Key::Space => { self.process_keyevent_space(); false },
+            Key::Escape => { self.process_keyevent_escape(); true },
+            Key::Tab => { self.process_keyevent_tab(); true },
+            Key::Down => { self.process_keyevent_move(1); true },
+            Key::Up => { self.process_keyevent_move(-1); true },
+            Key::Copy => { self.process_keyevent_copy(); true },
+            Key::Char(ch) => {
+                if ch.is_alphanumeric() {
+                    self.process_keyevent_char(ch);
+                } else {
+                    trace!("Ignore char: {}", ch);
+                }
+                false
+            },
+        }
+    }
+
+    /// Process event sent from worker threads via `Notifier`
+    pub fn process_event(&mut self, event: AppEvent) {
+        trace!("Processing app event: {:?}", event);
+        match event {
+            AppEvent::RunningDone => self.process_running_callback(),
+            AppEvent::RunningBatch => self.process_running_batch(),
+            AppEvent::RealtimeDone(text) => self.process_running_text_realtime_callback(&text),
+            AppEvent::RealtimeDebounced(text) => self.process_text_realtime_debounced(&text),
+        }
+    }
+
+    /// Whether the controller is waiting for text input
+    pub fn is_entering(&self) -> bool {
+        if let Status::Entering{..} = self.status { true } else { false }
+    }
+
+    /// Reset to initial status and show, optionally quicksend text from clipboard
+    pub fn reset(&mut self, send_clipboard: bool) {
+        trace!("Resetting window: {}", send_clipboard);
+        self.cancel_pending();
+        self.cancel_streaming();
+        self.ctx.reset();
+        self.status = Status::Initial;
+        if send_clipboard {
+            if let Err(error) = self.ctx.quicksend_from_clipboard() {
+                warn!("Unable to get content from clipboard: {}", error.display_chain());
+            } else {
+                self.status = Status::Default;
+            }
+        }
+        self.visible = true;
+        self.update_ui();
+    }
+
+    /// Reset and quicksend given text, instead of clipboard content
+    pub fn reset_quicksend(&mut self, text: &str) {
+        trace!("Resetting window with text: {:?}", text);
+        self.cancel_pending();
+        self.cancel_streaming();
+        self.ctx.reset();
+        self.status = match self.ctx.quicksend(&Item { title: text.into(), .. Item::default() }) {
+            Ok(_) => Status::Default,
+            Err(error) => Status::Error(Rc::new(error)),
+        };
+        self.visible = true;
+        self.update_ui();
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+        self.update_ui();
+    }
+
+    pub fn status_info(&self) -> StatusInfo {
+        let vm = self.view_model();
+        StatusInfo {
+            visible: vm.visible,
+            state: match self.status {
+                Status::Initial => "initial",
+                Status::Running(_) => "running",
+                Status::Error(_) => "error",
+                Status::Default => "default",
+                Status::Filtering{..} => "filtering",
+                Status::Entering{..} => "entering",
+            }.into(),
+            filter_text: vm.filter_text,
+            reference: vm.reference,
+            items: vm.items.iter().map(|x| x.item.title.clone()).collect(),
+        }
+    }
+
+    /// Process request from IPC, `Request::Reload` is left to the frontend (see `take_reload_request`)
+    pub fn process_request(&mut self, request: &Request) -> Response {
+        match request {
+            Request::Show => self.reset(false),
+            Request::Hide => self.hide(),
+            Request::Quicksend { text } => self.reset_quicksend(text),
+            Request::Status => return Response::status(self.status_info()),
+            Request::Reload => self.reload_requested = true,
+        }
+        Response::ok()
+    }
+
+    /// Return whether reloading is requested since last call,
+    /// the frontend should then reload with the new config (see `reload`)
+    pub fn take_reload_request(&mut self) -> bool {
+        std::mem::replace(&mut self.reload_requested, false)
+    }
+
+    /// Reset and list all items at once, instead of waiting for filter text
+    pub fn reset_listing(&mut self) {
+        self.reset(false);
+        self.status = Status::Default;
+        self.update_ui();
+    }
+
+    /// Apply `[core]` options of the controller, and record the config error (if any)
+    fn apply_config(&mut self, config: &Config) {
+        let global_config = config.partial(&["core"]).unwrap();
+        self.filter_timeout = std::time::Duration::from_millis(
+            global_config.get::<u64>(&["filter_timeout"]).unwrap());
+        self.set_realtime_options(
+            std::time::Duration::from_millis(global_config.get::<u64>(&["realtime_debounce"]).unwrap()),
+            global_config.get::<usize>(&["realtime_cache_size"]).unwrap());
+        self.config_error = config.error().map(|x| Rc::new(Error::from(x)));
+        self.updated = true;
+    }
+
+    /// Reload all actions and options with new config
+    pub fn reload(&mut self, config: &Config) {
+        match Matcher::from_config(config, None) {
+            Ok(matcher) => self.matcher = matcher,
+            Err(error) => warn!("Unable to load statistic file: {}", error),
+        }
+        self.apply_config(config);
+        self.ctx.apply_config(config);
+        self.ctx.reload(config);
+        self.realtime_cache = None;
+    }
+
+    /// Reload actions of given providers, e.g. when their files are changed
+    pub fn reload_providers(&mut self, config: &Config, providers: &[Provider]) {
+        self.ctx.reload_providers(config, providers);
+        self.realtime_cache = None;
+    }
+
+    pub fn new(ctx: Context, matcher: Matcher, filter_timeout: std::time::Duration, notifier: Notifier) -> Controller {
+        Controller {
+            ctx: ctx,
+            status: Status::Initial,
+            filter_timeout: filter_timeout,
+            matcher: matcher,
+            notifier: notifier,
+            streaming: None,
+            visible: false,
+            entry_text: String::new(),
+            updated: true,
+            realtime_debouncer: None,
+            realtime_cache: None,
+            realtime_cache_size: 0,
+            config_error: None,
+            reload_requested: false,
+        }
+    }
+
+    /// Set delay before running realtime actions after entry text is changed,
+    /// and the number of cached realtime results for each action (0 to disable)
+    pub fn set_realtime_options(&mut self, debounce: std::time::Duration, cache_size: usize) {
+        if debounce == std::time::Duration::from_millis(0) {
+            self.realtime_debouncer = None;
+        } else if let Some(ref debouncer) = self.realtime_debouncer {
+            debouncer.set_delay(debounce);
+        } else {
+            self.realtime_debouncer = Some(Debouncer::new(debounce, self.notifier.clone()));
+        }
+        self.realtime_cache_size = cache_size;
+        self.realtime_cache = None;
+    }
+
+    /// Create controller with context and matcher from config
+    pub fn from_config(config: &Config, clipboard: Box<dyn Clipboard>, notifier: Notifier) -> Controller {
+        let matcher = Matcher::from_config(config, None).unwrap();
+
+        let mut controller = Controller::new(
+            Context::new(&config, clipboard), matcher, std::time::Duration::from_millis(0), notifier);
+        controller.apply_config(config);
+        controller
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::mcore::action::{Action, RunContext};
+    use crate::mcore::lrudb::LruDB;
+    use crate::mcore::clipboard::MemoryClipboard;
+
+    struct EchoAction {}
+
+    impl Action for EchoAction {
+        fn runnable_arg(&self) -> bool { true }
+        fn run_arg(&self, text: &str) -> ActionResult {
+            Ok(vec![Item { title: format!("echo {}", text), .. Item::default() }])
+        }
+    }
+
+    struct ListAction {}
+
+    impl Action for ListAction {
+        fn runnable_bare(&self) -> bool { true }
+        fn run_bare(&self) -> ActionResult {
+            Ok(vec![Item { title: "first".into(), .. Item::default() },
+                    Item { title: "second".into(), .. Item::default() }])
+        }
+    }
+
+    struct FailAction {}
+
+    impl Action for FailAction {
+        fn runnable_bare(&self) -> bool { true }
+        fn run_bare(&self) -> ActionResult {
+            bail!("Failed on purpose")
+        }
+    }
+
+    /// Suggests "suggest {text}", counting the runs
+    struct SuggestAction {
+        count: Arc<AtomicUsize>,
+    }
+
+    impl Action for SuggestAction {
+        fn runnable_arg(&self) -> bool { true }
+        fn runnable_arg_realtime(&self) -> bool { true }
+        fn run_arg_realtime(&self, text: &str) -> ActionResult {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![Item { title: format!("suggest {}", text), .. Item::default() }])
+        }
+    }
+
+    /// Sleeps until cancelled, or ignores cancellation
+    struct SlowAction {
+        cancelled: Arc<AtomicBool>,
+        ignore_cancel: bool,
+        timeout: Option<Duration>,
+    }
+
+    impl Action for SlowAction {
+        fn runnable_bare(&self) -> bool { true }
+        fn timeout(&self) -> Option<Duration> { self.timeout }
+        fn run_bare(&self) -> ActionResult { unimplemented!() }
+        fn run_bare_cancellable(&self, token: &CancelToken, _: &RunContext) -> ActionResult {
+            for _ in 0..500 {
+                if token.is_cancelled() && !self.ignore_cancel {
+                    self.cancelled.store(true, Ordering::SeqCst);
+                    bail!("Cancelled");
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    /// Sends the first batch immediately, and the rest after released
+    struct StreamAction {
+        release: Arc<AtomicBool>,
+    }
+
+    impl Action for StreamAction {
+        fn runnable_bare(&self) -> bool { true }
+        fn run_bare(&self) -> ActionResult { unimplemented!() }
+        fn run_bare_streaming(&self, sink: &dyn Fn(Vec<Item>), _: &CancelToken, _: &RunContext) -> ActionResult {
+            sink(vec![Item { title: "a1".into(), .. Item::default() },
+                      Item { title: "a2".into(), .. Item::default() }]);
+            while !self.release.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Ok(vec![Item { title: "b2".into(), .. Item::default() }])
+        }
+    }
+
+    struct TestController {
+        controller: Controller,
+        events: mpsc::Receiver<AppEvent>,
+        statistic_file: std::path::PathBuf,
+    }
+
+    impl Drop for TestController {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.statistic_file);
+        }
+    }
+
+    impl TestController {
+        fn new(name: &str) -> TestController {
+            TestController::with_items(name, vec![
+                Item { title: "Echo".into(), action: Some(Arc::new(EchoAction{})), .. Item::default() },
+                Item { title: "List".into(), action: Some(Arc::new(ListAction{})), .. Item::default() },
+                Item { title: "Fail".into(), action: Some(Arc::new(FailAction{})), .. Item::default() },
+            ], None)
+        }
+
+        fn with_items(name: &str, items: Vec<Item>, timeout: Option<Duration>) -> TestController {
+            let mut ctx = Context::with_items(items, LruDB::new(None).unwrap(), 10,
+                                              Box::new(MemoryClipboard::default()));
+            ctx.set_timeouts(timeout, timeout);
+            let statistic_file = std::env::temp_dir().join(
+                format!("minions-controller-test-{}-{}.dat", std::process::id(), name));
+            let matcher = Matcher::new(&statistic_file, "test-salt", None).unwrap();
+
+            let (send_ch, recv_ch) = mpsc::channel::<AppEvent>();
+            let send_ch = Mutex::new(send_ch);
+            let notifier: Notifier = Arc::new(move |event| {
+                send_ch.lock().unwrap().send(event).unwrap();
+            });
+
+            let mut controller = Controller::new(ctx, matcher, std::time::Duration::from_millis(0), notifier);
+            controller.reset(false);
+            TestController {
+                controller: controller,
+                events: recv_ch,
+                statistic_file: statistic_file,
+            }
+        }
+
+        fn keys(&mut self, keys: &[Key]) {
+            for key in keys {
+                self.controller.process_key(*key);
+            }
+        }
+
+        fn type_text(&mut self, text: &str) {
+            for ch in text.chars() {
+                self.controller.process_key(Key::Char(ch));
+            }
+        }
+
+        fn wait_event(&mut self) {
+            let event = self.events.recv_timeout(Duration::from_secs(5)).unwrap();
+            self.controller.process_event(event);
+        }
+
+        fn titles(&self) -> Vec<String> {
+            self.controller.view_model().items.iter()
+                .map(|x| x.item.title.clone()).collect()
+        }
+    }
+
+    #[test]
+    fn controller_select_test() {
+        let mut t = TestController::new("select");
+        let vm = t.controller.view_model();
+        assert!(vm.visible);
+        assert!(t.titles().is_empty());
+
+        t.type_text("list");
+        let vm = t.controller.view_model();
+        assert_eq!(vm.filter_text, "list");
+        assert_eq!(vm.highlight, 0);
+        assert_eq!(t.titles(), vec!["List"]);
+        assert_eq!(vm.items[0].highlights, vec![0, 1, 2, 3]);
+
+        t.keys(&[Key::Enter]);
+        assert!(t.controller.view_model().spinning);
+        t.wait_event();
+        let vm = t.controller.view_model();
+        assert!(!vm.spinning);
+        assert!(vm.visible);
+        assert_eq!(t.titles(), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn controller_enter_text_test() {
+        let mut t = TestController::new("enter_text");
+        t.type_text("echo");
+        t.keys(&[Key::Space]);
+        assert!(t.controller.is_entering());
+        let vm = t.controller.view_model();
+        assert_eq!(vm.action.unwrap().title, "Echo");
+        if let EntryView::Editable = vm.entry {} else { panic!("Entry should be editable"); }
+
+        // chars are inserted into the entry by frontend, not filtering
+        t.type_text("hello");
+        t.controller.process_entry_text_changed("hello");
+        t.keys(&[Key::Enter]);
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["echo hello"]);
+    }
+
+    #[test]
+    fn controller_quicksend_test() {
+        let mut t = TestController::new("quicksend");
+        t.type_text("list");
+        t.keys(&[Key::Tab]);
+        let vm = t.controller.view_model();
+        assert_eq!(vm.reference, Some("List".into()));
+        assert_eq!(t.titles(), vec!["Echo"]);
+
+        t.keys(&[Key::Down, Key::Enter]);
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["echo List"]);
+    }
+
+    #[test]
+    fn controller_escape_test() {
+        let mut t = TestController::new("escape");
+        t.type_text("fail");
+        t.keys(&[Key::Enter]);
+        t.wait_event();
+        assert!(t.controller.view_model().error.is_some());
+
+        t.keys(&[Key::Escape]);
+        assert!(t.controller.view_model().error.is_none());
+        assert_eq!(t.titles(), vec!["Echo", "List", "Fail"]);
+
+        t.keys(&[Key::Escape]);
+        assert!(t.controller.view_model().visible);
+        t.keys(&[Key::Escape]);
+        assert!(!t.controller.view_model().visible);
+
+        t.controller.reset(false);
+        assert!(t.controller.view_model().visible);
+    }
+
+    #[test]
+    fn controller_move_test() {
+        let mut t = TestController::new("move");
+        t.keys(&[Key::Down]);
+        assert_eq!(t.controller.view_model().highlight, 0);
+        t.keys(&[Key::Down, Key::Down, Key::Down]);
+        assert_eq!(t.controller.view_model().highlight, 2);
+        t.keys(&[Key::Up, Key::Up, Key::Up, Key::Up]);
+        assert_eq!(t.controller.view_model().highlight, 0);
+
+        t.keys(&[Key::Copy]);
+        assert!(!t.controller.view_model().visible);
+    }
+
+    #[test]
+    fn controller_request_test() {
+        let mut t = TestController::new("request");
+        assert!(t.controller.process_request(&Request::Quicksend { text: "hello".into() }).ok);
+        let status = t.controller.status_info();
+        assert_eq!(status.state, "default");
+        assert_eq!(status.reference, Some("hello".into()));
+        assert_eq!(status.items, vec!["Echo".to_string()]);
+
+        assert!(t.controller.process_request(&Request::Hide).ok);
+        let response = t.controller.process_request(&Request::Status);
+        assert!(!response.status.unwrap().visible);
+
+        assert!(t.controller.process_request(&Request::Show).ok);
+        assert!(t.controller.status_info().visible);
+        assert!(!t.controller.take_reload_request());
+        assert!(t.controller.process_request(&Request::Reload).ok);
+        assert!(t.controller.take_reload_request());
+        assert!(!t.controller.take_reload_request());
+    }
+
+    #[test]
+    fn controller_cancel_test() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut t = TestController::with_items("cancel", vec![
+            Item { title: "Slow".into(),
+                   action: Some(Arc::new(SlowAction{ cancelled: cancelled.clone(), ignore_cancel: false,
+                                                     timeout: None })),
+                   .. Item::default() },
+        ], None);
+        t.type_text("slow");
+        t.keys(&[Key::Enter]);
+        assert!(t.controller.view_model().spinning);
+        t.keys(&[Key::Escape]);
+        assert!(!t.controller.view_model().spinning);
+
+        let start = std::time::Instant::now();
+        while !cancelled.load(Ordering::SeqCst) {
+            assert!(start.elapsed() < Duration::from_secs(3));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // cancelled action should not call back
+        assert!(t.events.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn controller_timeout_test() {
+        let mut t = TestController::with_items("timeout", vec![
+            Item { title: "Slow".into(),
+                   action: Some(Arc::new(SlowAction{ cancelled: Arc::new(AtomicBool::new(false)),
+                                                     ignore_cancel: true, timeout: None })),
+                   .. Item::default() },
+            Item { title: "Quick".into(),
+                   action: Some(Arc::new(SlowAction{ cancelled: Arc::new(AtomicBool::new(false)),
+                                                     ignore_cancel: true,
+                                                     timeout: Some(Duration::from_millis(50)) })),
+                   .. Item::default() },
+        ], Some(Duration::from_millis(50)));
+        t.type_text("slow");
+        t.keys(&[Key::Enter]);
+        t.wait_event();
+        assert!(t.controller.view_model().error.is_some());
+
+        // timeout of the action overrides the global one
+        t.controller.ctx.set_timeouts(None, None);
+        t.controller.reset(false);
+        t.type_text("quick");
+        t.keys(&[Key::Enter]);
+        t.wait_event();
+        assert!(t.controller.view_model().error.is_some());
+    }
+
+    fn suggest_controller(name: &str, count: &Arc<AtomicUsize>) -> TestController {
+        let mut t = TestController::with_items(name, vec![
+            Item { title: "Suggest".into(),
+                   action: Some(Arc::new(SuggestAction{ count: count.clone() })),
+                   .. Item::default() },
+        ], None);
+        t.type_text("sug");
+        t.keys(&[Key::Enter]);
+        assert!(t.controller.is_entering());
+        t
+    }
+
+    #[test]
+    fn controller_realtime_cache_test() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut t = suggest_controller("realtime-cache", &count);
+        t.controller.set_realtime_options(Duration::from_millis(0), 4);
+
+        t.controller.process_entry_text_changed("a");
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["suggest a".to_string()]);
+        t.controller.process_entry_text_changed("ab");
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["suggest ab".to_string()]);
+
+        t.controller.process_entry_text_changed("a");
+        assert_eq!(t.titles(), vec!["suggest a".to_string()]);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        // result of outdated text is discarded
+        t.controller.process_event(AppEvent::RealtimeDone("ab".into()));
+        assert_eq!(t.titles(), vec!["suggest a".to_string()]);
+
+        // the cache only keeps results of the action being entered
+        let other = Item { action: Some(Arc::new(SuggestAction{ count: count.clone() })), .. Item::default() };
+        assert_eq!(t.controller.realtime_cache_of(&other).unwrap().len(), 0);
+        t.controller.process_entry_text_changed("ab");
+        t.wait_event();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // results of previous text are cleared
+        t.controller.process_entry_text_changed("");
+        assert!(t.titles().is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn controller_realtime_debounce_test() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut t = suggest_controller("realtime-debounce", &count);
+        t.controller.set_realtime_options(Duration::from_millis(50), 0);
+
+        t.controller.process_entry_text_changed("a");
+        t.controller.process_entry_text_changed("ab");
+        t.wait_event();
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["suggest ab".to_string()]);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // "a" is superseded by "ab" instead of being notified later
+        assert!(t.events.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // the same debouncer is kept with the new delay
+        let delay = t.controller.realtime_debouncer.as_ref().unwrap().delay.clone();
+        t.controller.set_realtime_options(Duration::from_millis(20), 0);
+        assert_eq!(*delay.lock().unwrap(), Duration::from_millis(20));
+        t.controller.process_entry_text_changed("abc");
+        t.wait_event();
+        t.wait_event();
+        assert_eq!(t.titles(), vec!["suggest abc".to_string()]);
+    }
+
+    #[test]
+    fn controller_take_update_test() {
+        let mut t = TestController::new("take_update");
+        assert!(t.controller.take_update().is_some());
+        assert!(t.controller.take_update().is_none());
+        t.type_text("e");
+        assert!(t.controller.take_update().is_some());
+        assert!(t.controller.take_update().is_none());
+    }
+
+    #[test]
+    fn controller_streaming_test() {
+        let release = Arc::new(AtomicBool::new(false));
+        let mut t = TestController::with_items("streaming", vec![
+            Item { title: "Stream".into(), action: Some(Arc::new(StreamAction{ release: release.clone() })),
+                   .. Item::default() },
+        ], None);
+        t.type_text("stream");
+        t.keys(&[Key::Enter]);
+        t.wait_event();
+        // first batch is displayed while still running
+        assert_eq!(t.titles(), vec!["a1", "a2"]);
+        assert!(t.controller.view_model().spinning);
+
+        t.type_text("2");
+        assert_eq!(t.titles(), vec!["a2"]);
+
+        release.store(true, Ordering::SeqCst);
+        t.wait_event();
+        let vm = t.controller.view_model();
+        assert!(!vm.spinning);
+        assert_eq!(vm.filter_text, "2");
+        assert_eq!(t.titles().len(), 2);
+    }
+
+    #[test]
+    fn controller_builtin_test() {
+        let mut t = TestController::with_items("builtin", vec![
+            Item { title: "Echo".into(), action: Some(Arc::new(EchoAction{})), .. Item::default() },
+            Item { title: "Copy".into(), action: Some(Arc::new(BuiltinAction::Copy("copied".into()))),
+                   .. Item::default() },
+            Item { title: "Send".into(), action: Some(Arc::new(BuiltinAction::Quicksend("sent".into()))),
+                   .. Item::default() },
+            Item { title: "Reload".into(), action: Some(Arc::new(BuiltinAction::Reload)), .. Item::default() },
+        ], None);
+        t.type_text("send");
+        t.keys(&[Key::Enter]);
+        let vm = t.controller.view_model();
+        assert!(!vm.spinning);
+        assert_eq!(vm.reference, Some("sent".into()));
+        assert_eq!(t.titles(), vec!["Echo"]);
+
+        t.controller.reset(false);
+        t.type_text("copy");
+        t.keys(&[Key::Enter]);
+        assert!(!t.controller.view_model().visible);
+        t.controller.ctx.quicksend_from_clipboard().unwrap();
+        assert_eq!(t.controller.ctx.reference, Some("copied".into()));
+
+        t.controller.reset(false);
+        t.type_text("reload");
+        t.keys(&[Key::Enter]);
+        assert!(t.controller.take_reload_request());
+    }
+
+    #[test]
+    fn controller_config_error_test() {
+        let mut t = TestController::new("config_error");
+        let path = std::env::temp_dir().join(
+            format!("minions-controller-test-{}-config.toml", std::process::id()));
+        std::fs::write(&path, "[core\n").unwrap();
+        t.controller.apply_config(&Config::with_layers(&path, None, &[]));
+        let _ = std::fs::remove_file(&path);
+
+        t.controller.reset(false);
+        let vm = t.controller.view_model();
+        assert!(vm.error.unwrap().to_string().contains("whole file ignored"));
+        assert_eq!(t.controller.filter_timeout, Duration::from_millis(800));
+
+        t.controller.apply_config(&Config::with_layers(std::path::Path::new(""), None, &[]));
+        assert!(t.controller.view_model().error.is_none());
+    }
+}
@@ -0,0 +1,124 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-25
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-09
+*/
+
+use std::sync::{Arc, Mutex};
+
+use crate::frontend::controller::{Controller, Notifier};
+use crate::mcore::action::{Action, ActionResult};
+use crate::mcore::item::Item;
+use crate::mcore::context::Context;
+use crate::mcore::matcher::Matcher;
+use crate::mcore::lrudb::LruDB;
+use crate::mcore::config::Config;
+use crate::mcore::clipboard::Clipboard;
+
+/// Selecting a candidate saves it, and returns nothing (which closes the window)
+struct SelectLine {
+    line: String,
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl Action for SelectLine {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn run_bare(&self) -> ActionResult {
+        *self.selected.lock().unwrap() = Some(self.line.clone());
+        Ok(Vec::new())
+    }
+}
+
+/// Dmenu-like mode: choose one line from given candidates
+pub struct Dmenu {
+    lines: Vec<String>,
+    scope: String,
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl Dmenu {
+
+    /// `scope` separates statistics of different dmenu usages,
+    /// so that they would not affect each other (or the launcher)
+    pub fn new(lines: Vec<String>, scope: &str) -> Dmenu {
+        Dmenu {
+            lines: lines.into_iter().filter(|x| !x.is_empty()).collect(),
+            scope: scope.into(),
+            selected: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn items(&self) -> Vec<Item> {
+        self.lines.iter().map(|line| Item {
+            title: line.clone(),
+            action: Some(Arc::new(SelectLine {
+                line: line.clone(),
+                selected: self.selected.clone(),
+            })),
+            .. Item::default()
+        }).collect()
+    }
+
+    /// Build a controller listing all candidates
+    pub fn build_controller(&self, config: &Config, clipboard: Box<dyn Clipboard>,
+                            notifier: Notifier) -> Controller {
+        let global_config = config.partial(&["core"]).unwrap();
+        let matcher = Matcher::from_config(config, Some(&format!("dmenu:{}", self.scope))).unwrap();
+
+        let ctx = Context::with_items(self.items(), LruDB::new(None).unwrap(), 0, clipboard);
+        let mut controller = Controller::new(
+            ctx, matcher,
+            std::time::Duration::from_millis(global_config.get::<u64>(&["filter_timeout"]).unwrap()),
+            notifier);
+        controller.reset_listing();
+        controller
+    }
+
+    /// The chosen line, if any
+    pub fn selected(&self) -> Option<String> {
+        self.selected.lock().unwrap().clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::view::Key;
+    use crate::mcore::clipboard::NullClipboard;
+
+    #[test]
+    fn dmenu_select_test() {
+        let statistic_file = std::env::temp_dir().join(
+            format!("minions-dmenu-test-{}.dat", std::process::id()));
+        let dmenu = Dmenu::new(vec!["apple".into(), "".into(), "banana".into()], "test");
+        let ctx = Context::with_items(dmenu.items(), LruDB::new(None).unwrap(), 0,
+                                      Box::new(NullClipboard{}));
+        let matcher = Matcher::new(&statistic_file, "salt:dmenu:test", None).unwrap();
+        let (send_ch, recv_ch) = std::sync::mpsc::channel();
+        let send_ch = Mutex::new(send_ch);
+        let mut controller = Controller::new(ctx, matcher, std::time::Duration::from_millis(0),
+                                             Arc::new(move |event| {
+                                                 send_ch.lock().unwrap().send(event).unwrap();
+                                             }));
+        controller.reset_listing();
+
+        let vm = controller.view_model();
+        assert!(vm.visible);
+        assert_eq!(vm.items.len(), 2);
+
+        for ch in "ban".chars() {
+            controller.process_key(Key::Char(ch));
+        }
+        controller.process_key(Key::Enter);
+        let event = recv_ch.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        controller.process_event(event);
+
+        assert!(!controller.view_model().visible);
+        assert_eq!(dmenu.selected(), Some("banana".into()));
+
+        let _ = std::fs::remove_file(&statistic_file);
+    }
+}
@@ -0,0 +1,280 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-21
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate glib;
+extern crate libc;
+extern crate glib_sys;
+
+use std;
+use std::ffi;
+use std::sync::Arc;
+use std::cell::RefCell;
+
+use crate::frontend::gdk;
+use crate::frontend::gtk;
+use crate::frontend::gtk::prelude::*;
+
+use crate::frontend::app::MinionsApp;
+use crate::frontend::controller::{Controller, AppEvent, Notifier};
+use crate::frontend::ipc;
+use crate::frontend::watcher::{Watcher, Reload};
+use crate::frontend::view::Key;
+use crate::frontend::ui::MinionsUI;
+use crate::frontend::clipboard::GtkClipboard;
+use crate::mcore::config::Config;
+use crate::mcore::clipboard::Clipboard;
+
+use error_chain::ChainedError;
+
+
+thread_local! {
+    pub static APP: RefCell<Option<MinionsApp<MinionsUI>>> = RefCell::new(None);
+    /// Currently bound shortcuts, unbound before re-binding
+    static SHORTCUTS: RefCell<Vec<ffi::CString>> = RefCell::new(Vec::new());
+}
+
+
+#[link(name="keybinder-3.0")]
+extern {
+    fn keybinder_init();
+    fn keybinder_bind(keystring: *const libc::c_char,
+                      handler: extern fn(*const libc::c_char, *mut libc::c_void),
+                      user_data: *mut libc::c_void) -> glib_sys::gboolean;
+    fn keybinder_unbind_all(keystring: *const libc::c_char);
+}
+
+extern fn keybinder_callback_show(_: *const libc::c_char, _: *mut libc::c_void) {
+    trace!("keybinder callback: show");
+    glib::idle_add( move || {
+        APP.with(|app| {
+            if let Some(ref mut app) = *app.borrow_mut() {
+                app.reset_window(false);
+            }
+        });
+        Continue(false)
+    });
+}
+
+extern fn keybinder_callback_show_clipboard(_: *const libc::c_char, _: *mut libc::c_void) {
+    trace!("keybinder callback: show with clipboard");
+    glib::idle_add( move || {
+        APP.with(|app| {
+            if let Some(ref mut app) = *app.borrow_mut() {
+                app.reset_window(true);
+            }
+        });
+        Continue(false)
+    });
+}
+
+fn translate_keyevent(event: &gdk::EventKey) -> Option<Key> {
+    let key = event.get_keyval();
+    let modi = event.get_state();
+    if key == gdk::enums::key::Return {
+        Some(Key::Enter)
+    } else if key == gdk::enums::key::space {
+        Some(Key::Space)
+    } else if key == gdk::enums::key::Escape {
+        Some(Key::Escape)
+    } else if key == gdk::enums::key::Tab {
+        Some(Key::Tab)
+    } else if key == 'j' as u32 && modi.contains(gdk::ModifierType::CONTROL_MASK) {
+        Some(Key::Down)
+    } else if key == 'k' as u32 && modi.contains(gdk::ModifierType::CONTROL_MASK) {
+        Some(Key::Up)
+    } else if key == 'c' as u32 && modi.contains(gdk::ModifierType::CONTROL_MASK) {
+        Some(Key::Copy)
+    } else if key == gdk::enums::key::Down {
+        Some(Key::Down)
+    } else if key == gdk::enums::key::Up {
+        Some(Key::Up)
+    } else if let Some(ch) = gdk::keyval_to_unicode(key) {
+        Some(Key::Char(ch))
+    } else {
+        None
+    }
+}
+
+fn notifier() -> Notifier {
+    Arc::new(|event: AppEvent| {
+        glib::idle_add(move || {
+            let event = event.clone();
+            APP.with(|app| {
+                if let Some(ref mut app) = *app.borrow_mut() {
+                    app.process_event(event);
+                }
+            });
+            Continue(false)
+        });
+    })
+}
+
+fn connect_signals(app: &MinionsApp<MinionsUI>) {
+    app.ui.window.connect_key_press_event(move |_, event| {
+        trace!("Key pressed: {:?}/{:?}", event.get_keyval(), event.get_state());
+        APP.with(|app| {
+            if let Some(ref mut app) = *app.borrow_mut() {
+                if let Some(key) = translate_keyevent(event) {
+                    return Inhibit(app.process_key(key));
+                }
+            }
+            Inhibit(false)
+        })
+    });
+
+    app.ui.textentry.connect_changed(move |_| {
+        glib::idle_add(move || {
+            APP.with(|app| {
+                if let Some(ref mut app) = *app.borrow_mut() {
+                    app.process_entry_text_changed()
+                }
+            });
+            Continue(false)
+        });
+    });
+
+    app.ui.window.connect_delete_event(move |_, _| {
+        gtk::main_quit();
+        Inhibit(false)
+    });
+}
+
+/// Bind shortcuts in config, replacing previously bound ones
+fn bind_shortcuts(config: &Config) {
+    let global_config = config.partial(&["core"]).unwrap();
+    SHORTCUTS.with(|shortcuts| {
+        let mut shortcuts = shortcuts.borrow_mut();
+        for keys in shortcuts.drain(..) {
+            unsafe { keybinder_unbind_all(keys.as_ptr()) };
+        }
+
+        let handlers: [(&str, extern fn(*const libc::c_char, *mut libc::c_void)); 2] = [
+            ("shortcut_show", keybinder_callback_show),
+            ("shortcut_show_quicksend", keybinder_callback_show_clipboard),
+        ];
+        for &(name, handler) in handlers.iter() {
+            let keys = global_config.get::<String>(&[name]).unwrap();
+            if keys.len() > 0 {
+                info!("Binding shortcut for {}: {}", name, keys);
+                let s = ffi::CString::new(keys).unwrap();
+                if unsafe { keybinder_bind(s.as_ptr(), handler, std::ptr::null_mut()) } == 0 {
+                    warn!("Unable to bind shortcut for {}", name);
+                }
+                shortcuts.push(s);
+            } else {
+                warn!("No shortcut defined for {}", name);
+            }
+        }
+    });
+}
+
+/// Show the window once with the controller built by `build`, until it's hidden or closed
+pub fn run_once<F>(build: F)
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    gtk::init().expect("Failed to initialize GTK");
+
+    let app = MinionsApp::new(MinionsUI::new(), build(Box::new(GtkClipboard{}), notifier()));
+    if !app.ui.window.is_visible() {
+        return;
+    }
+    connect_signals(&app);
+    app.ui.window.connect_hide(move |_| gtk::main_quit());
+
+    APP.with(|g_app| *g_app.borrow_mut() = Some(app) );
+    gtk::main();
+}
+
+/// Run the GTK frontend, until the window is closed
+/// Process `request` at startup if given (e.g. forwarded from command line)
+pub fn run(configpath: &std::path::Path, request: Option<&ipc::Request>) {
+    gtk::init().expect("Failed to initialize GTK");
+
+    let config = Config::new(configpath);
+    let global_config = config.partial(&["core"]).unwrap();
+
+    let mut app = MinionsApp::new(MinionsUI::new(),
+                              Controller::from_config(&config, Box::new(GtkClipboard{}), notifier()));
+    connect_signals(&app);
+
+    unsafe { keybinder_init() };
+    bind_shortcuts(&config);
+
+    let watcher = if global_config.get::<bool>(&["watch_files"]).unwrap() {
+        let callback = Arc::new(move |reload: Reload| {
+            let mut reload = Some(reload);
+            glib::idle_add(move || {
+                if let Some(reload) = reload.take() {
+                    APP.with(|app| {
+                        if let Some(ref mut app) = *app.borrow_mut() {
+                            app.reload(&reload);
+                        }
+                    });
+                }
+                Continue(false)
+            });
+        });
+        match Watcher::new(configpath, &config, callback) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                warn!("Unable to watch files: {}", error.display_chain());
+                None
+            },
+        }
+    } else {
+        None
+    };
+    // shortcuts and watched files are also reloaded, the watcher is kept by the app
+    app.set_reload(configpath, Some(Box::new(move |reload, configpath, config| {
+        if let Reload::All = reload {
+            bind_shortcuts(config);
+        }
+        if let Some(ref watcher) = watcher {
+            watcher.update(configpath, config);
+        }
+    })));
+
+    let socket_path = global_config.get_filename(&["control_socket"]).unwrap();
+    if socket_path.as_os_str().len() > 0 {
+        let dispatcher: ipc::Dispatcher = Arc::new(move |call: ipc::Call| {
+            let mut call = Some(call);
+            glib::idle_add(move || {
+                if let Some(call) = call.take() {
+                    APP.with(|app| {
+                        if let Some(ref mut app) = *app.borrow_mut() {
+                            let response = app.process_request(&call.request);
+                            call.respond(response);
+                        }
+                    });
+                }
+                Continue(false)
+            });
+        });
+        if let Err(error) = ipc::serve(&socket_path, dispatcher) {
+            warn!("Unable to start control socket: {}", error.display_chain());
+        }
+    }
+
+    if let Some(request) = request {
+        app.process_request(request);
+    } else if config.error().is_some() {
+        // show config errors at startup, instead of waiting for the shortcut
+        app.reset_window(false);
+    }
+
+    glib::source::unix_signal_add(1, move || {
+        APP.with(|app| {
+            if let Some(ref mut app) = *app.borrow_mut() {
+                info!("Received SIGHUP, reloading context");
+                app.reload(&Reload::All);
+            }
+        });
+        Continue(true)
+    });
+
+    APP.with(|g_app| *g_app.borrow_mut() = Some(app) );
+    gtk::main();
+}
@@ -0,0 +1,67 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-27
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-01-27
+*/
+
+extern crate libc;
+
+use std::io;
+use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::mcore::errors::*;
+
+/// Exclusive lock held by the running instance
+/// Released when dropped (or when the process exits)
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+
+    /// Try to lock `path` (with flock), return None if another instance holds the lock
+    pub fn try_acquire(path: &Path) -> Result<Option<InstanceLock>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).write(true).open(path)
+            .chain_err(|| format!("Unable to open lock file {:?}", path))?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                debug!("Lock file {:?} is held by another instance", path);
+                return Ok(None);
+            }
+            return Err(Error::with_chain(error, format!("Unable to lock {:?}", path)));
+        }
+
+        // for information only
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Some(InstanceLock { _file: file }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_lock_test() {
+        let path = std::env::temp_dir().join(
+            format!("minions-instance-test-{}.lock", std::process::id()));
+        let lock = InstanceLock::try_acquire(&path).unwrap();
+        assert!(lock.is_some());
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_none());
+        drop(lock);
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_some());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
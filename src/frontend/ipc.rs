@@ -0,0 +1,193 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-27
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-01-27
+*/
+
+extern crate serde_json;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::mcore::errors::*;
+
+/// Request sent to the running instance, one JSON object per line
+/// e.g. `{"command": "quicksend", "text": "hello"}`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Show the window, like `shortcut_show`
+    Show,
+    /// Hide the window
+    Hide,
+    /// Show the window with given text to quicksend, like `shortcut_show_quicksend`
+    Quicksend { text: String },
+    /// Reload config and actions, like SIGHUP
+    Reload,
+    /// Query current status
+    Status,
+}
+
+/// Current status of the app, replied to `Request::Status`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct StatusInfo {
+    pub visible: bool,
+    /// Name of current state, e.g. "filtering"
+    pub state: String,
+    pub filter_text: String,
+    pub reference: Option<String>,
+    /// Titles of displayed items
+    pub items: Vec<String>,
+}
+
+/// Reply to `Request`, one JSON object per line
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusInfo>,
+}
+
+impl Response {
+    pub fn ok() -> Response {
+        Response { ok: true, error: None, status: None }
+    }
+
+    pub fn error(msg: &str) -> Response {
+        Response { ok: false, error: Some(msg.into()), status: None }
+    }
+
+    pub fn status(status: StatusInfo) -> Response {
+        Response { ok: true, error: None, status: Some(status) }
+    }
+}
+
+/// A request waiting to be processed (in UI thread)
+pub struct Call {
+    pub request: Request,
+    responder: mpsc::Sender<Response>,
+}
+
+impl Call {
+    pub fn respond(self, response: Response) {
+        if self.responder.send(response).is_err() {
+            debug!("Client is gone before responding");
+        }
+    }
+}
+
+/// Called from the server thread to deliver `Call` to the UI thread
+pub type Dispatcher = Arc<dyn Fn(Call) + Send + Sync>;
+
+const RESPOND_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn handle_client(stream: UnixStream, dispatcher: Dispatcher) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                debug!("Received request: {:?}", request);
+                let (send_ch, recv_ch) = mpsc::channel();
+                dispatcher(Call { request: request, responder: send_ch });
+                recv_ch.recv_timeout(RESPOND_TIMEOUT)
+                    .unwrap_or_else(|_| Response::error("Timeout processing request"))
+            },
+            Err(error) => Response::error(&format!("Invalid request: {}", error)),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&response).unwrap())?;
+    }
+    Ok(())
+}
+
+/// Listen on unix socket `path` in a new thread, dispatch requests via `dispatcher`
+///
+/// Stale socket file is removed, but it fails if another instance is listening on it
+pub fn serve(path: &Path, dispatcher: Dispatcher) -> Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("Another instance is listening on {:?}", path);
+        }
+        debug!("Removing stale socket {:?}", path);
+        std::fs::remove_file(path)?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(path)
+        .chain_err(|| format!("Unable to listen on {:?}", path))?;
+    info!("Listening on {:?}", path);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let dispatcher = dispatcher.clone();
+                    thread::spawn(move || {
+                        if let Err(error) = handle_client(stream, dispatcher) {
+                            debug!("Error handling client: {}", error);
+                        }
+                    });
+                },
+                Err(error) => warn!("Unable to accept connection: {}", error),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Send a request to the instance listening on `path`, and wait for the response
+pub fn send(path: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(path)
+        .chain_err(|| format!("Unable to connect to {:?}, is Minions running?", path))?;
+    writeln!(stream, "{}", serde_json::to_string(request).unwrap())?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| Error::with_chain(e, "Invalid response"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_format_test() {
+        assert_eq!(serde_json::from_str::<Request>(r#"{"command": "show"}"#).unwrap(),
+                   Request::Show);
+        assert_eq!(serde_json::from_str::<Request>(r#"{"command": "quicksend", "text": "hi"}"#).unwrap(),
+                   Request::Quicksend { text: "hi".into() });
+        assert!(serde_json::from_str::<Request>(r#"{"command": "quicksend"}"#).is_err());
+        assert_eq!(serde_json::to_string(&Response::ok()).unwrap(), r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn serve_send_test() {
+        let path = std::env::temp_dir().join(
+            format!("minions-ipc-test-{}.sock", std::process::id()));
+        serve(&path, Arc::new(|call: Call| {
+            let response = match call.request {
+                Request::Hide => Response::ok(),
+                _ => Response::error("unsupported"),
+            };
+            call.respond(response);
+        })).unwrap();
+
+        assert_eq!(send(&path, &Request::Hide).unwrap(), Response::ok());
+        assert!(!send(&path, &Request::Reload).unwrap().ok);
+        assert!(serve(&path, Arc::new(|_| {})).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
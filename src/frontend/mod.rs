@@ -2,11 +2,29 @@
 * @Author: BlahGeek
 * @Date:   2017-04-22
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-02-04
+* @Last Modified time: 2020-02-06
 */
 
+#[cfg(feature = "gui")]
 extern crate gtk;
+#[cfg(feature = "gui")]
 extern crate gdk;
 
-pub mod ui;
+pub mod view;
+pub mod controller;
 pub mod app;
+pub mod dmenu;
+pub mod cli;
+pub mod ipc;
+pub mod instance;
+pub mod watcher;
+
+#[cfg(feature = "gui")]
+pub mod ui;
+#[cfg(feature = "gui")]
+pub mod clipboard;
+#[cfg(feature = "gui")]
+pub mod gui;
+
+#[cfg(feature = "tui")]
+pub mod tui;
@@ -0,0 +1,459 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-22
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+extern crate termion;
+extern crate libc;
+extern crate unicode_width;
+extern crate base64;
+
+use std;
+use std::io;
+use std::io::{Read, Write};
+use std::fs::File;
+use std::cmp;
+use std::cell::RefCell;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use self::termion::raw::{IntoRawMode, RawTerminal};
+use self::termion::screen::AlternateScreen;
+use self::termion::{clear, cursor, style};
+use self::unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::frontend::app::MinionsApp;
+use crate::frontend::controller::{Controller, AppEvent, Notifier};
+use crate::frontend::view::{View, ViewModel, EntryView, Key, split_highlights};
+use crate::frontend::watcher::{Watcher, Reload};
+use crate::mcore::config::Config;
+use crate::mcore::clipboard::Clipboard;
+use crate::mcore::errors::*;
+
+use error_chain::ChainedError;
+
+
+#[derive(Default)]
+struct TermState {
+    visible: bool,
+    spinning: bool,
+    entry: String,
+    entry_editable: bool,
+    filter_text: String,
+    action_name: Option<String>,
+    /// Reference text or error message
+    message: Option<String>,
+    /// Lines of listing items, and indices of highlighted chars in them
+    items: Vec<(String, Vec<usize>)>,
+    highlight: i32,
+}
+
+/// Terminal view, rendered on /dev/tty so that stdin/stdout are left untouched
+pub struct TermUI {
+    fd: RawFd,
+    out: RefCell<AlternateScreen<RawTerminal<File>>>,
+    state: RefCell<TermState>,
+}
+
+fn terminal_size(fd: RawFd) -> (usize, usize) {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 && size.ws_row > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+/// Make text printable in a single line of given width (in columns, e.g. CJK chars take two)
+fn single_line(text: &str, width: usize) -> String {
+    let mut ret = String::new();
+    let mut used = 0;
+    for ch in text.chars().map(|ch| if ch.is_control() { ' ' } else { ch }) {
+        used += ch.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+        ret.push(ch);
+    }
+    ret
+}
+
+impl TermUI {
+
+    pub fn new() -> io::Result<TermUI> {
+        let tty = termion::get_tty()?;
+        let fd = tty.as_raw_fd();
+        let out = AlternateScreen::from(tty.into_raw_mode()?);
+        Ok(TermUI {
+            fd: fd,
+            out: RefCell::new(out),
+            state: RefCell::new(TermState::default()),
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.state.borrow().visible
+    }
+
+    pub fn is_entry_editable(&self) -> bool {
+        self.state.borrow().entry_editable
+    }
+
+    /// Insert (Some) or delete (None) a char at the end of editable entry
+    pub fn edit_entry(&self, ch: Option<char>) {
+        let mut state = self.state.borrow_mut();
+        match ch {
+            Some(ch) => state.entry.push(ch),
+            None => { state.entry.pop(); },
+        }
+    }
+
+    /// Draw current state to the terminal
+    pub fn draw(&self) -> io::Result<()> {
+        let state = self.state.borrow();
+        let mut out = self.out.borrow_mut();
+        let (width, height) = terminal_size(self.fd);
+
+        write!(out, "{}{}", clear::All, cursor::Hide)?;
+
+        let mut header = match state.action_name {
+            Some(ref name) => format!("{} > ", name),
+            None => "> ".into(),
+        };
+        header += &state.entry;
+        let cursor_x = header.width();
+        if state.filter_text.len() > 0 {
+            header += &format!("    [{}]", state.filter_text);
+        }
+        if state.spinning {
+            header += "    ...";
+        }
+        write!(out, "{}{}{}{}", cursor::Goto(1, 1), style::Bold, single_line(&header, width), style::Reset)?;
+
+        if let Some(ref message) = state.message {
+            write!(out, "{}{}", cursor::Goto(1, 2), single_line(message, width))?;
+        }
+
+        let listbox_num = cmp::max(1, height as i32 - 3);
+        let highlight = state.highlight;
+        let mut display_start =
+            if highlight < (listbox_num / 2) { 0 }
+            else { highlight - (listbox_num / 2) };
+        let display_end = cmp::min(display_start + listbox_num, state.items.len() as i32);
+        if display_end - display_start < listbox_num {
+            display_start = cmp::max(0, display_end - listbox_num);
+        }
+
+        for i in display_start .. display_end {
+            let (ref text, ref highlights) = state.items[i as usize];
+            let line = single_line(text, width);
+            let row = (i - display_start + 3) as u16;
+            write!(out, "{}", cursor::Goto(1, row))?;
+            if i == highlight {
+                write!(out, "{}", style::Invert)?;
+            }
+            for (segment, highlighted) in split_highlights(&line, highlights) {
+                if highlighted {
+                    write!(out, "{}{}{}", style::Bold, segment, style::Reset)?;
+                    if i == highlight {
+                        write!(out, "{}", style::Invert)?;
+                    }
+                } else {
+                    write!(out, "{}", segment)?;
+                }
+            }
+            write!(out, "{}", style::Reset)?;
+        }
+
+        if state.entry_editable {
+            write!(out, "{}{}", cursor::Goto(cmp::min(cursor_x, width) as u16 + 1, 1), cursor::Show)?;
+        }
+        out.flush()
+    }
+}
+
+impl Drop for TermUI {
+    fn drop(&mut self) {
+        let _ = write!(self.out.borrow_mut(), "{}", cursor::Show);
+    }
+}
+
+impl View for TermUI {
+
+    fn render(&self, vm: &ViewModel) {
+        let mut state = self.state.borrow_mut();
+        state.visible = vm.visible;
+        state.spinning = vm.spinning;
+        match vm.entry {
+            EntryView::Item(ref item) => {
+                state.entry = item.as_ref().map(|x| x.title.clone()).unwrap_or(String::new());
+                state.entry_editable = false;
+            },
+            EntryView::Editable => {
+                if !state.entry_editable {
+                    state.entry = String::new();
+                    state.entry_editable = true;
+                }
+            },
+        }
+        state.filter_text = vm.filter_text.clone();
+        state.action_name = vm.action.as_ref().map(|x| x.title.clone());
+        state.message = None;
+        if let Some(ref reference) = vm.reference {
+            state.message = Some(reference.clone());
+            state.action_name = Some("Quicksend".into());
+        }
+        if let Some(ref error) = vm.error {
+            state.message = Some(error.display_chain().to_string());
+        }
+
+        state.items = vm.items.iter().map(|item_view| {
+            let item = &item_view.item;
+            let flag =
+                if item_view.selectable { ">" }
+                else if item_view.selectable_with_text { "A" }
+                else { " " };
+            let mut text = format!("{} {}", flag, item.title);
+            // after the flag and space
+            let highlights = item_view.highlights.iter().map(|x| x + 2).collect();
+            if let Some(ref subtitle) = item.subtitle {
+                if subtitle.len() > 0 {
+                    text += &format!("  - {}", subtitle);
+                }
+            }
+            if let Some(ref badge) = item.badge {
+                text += &format!("  [{}]", badge);
+            }
+            (text, highlights)
+        }).collect();
+        state.highlight = vm.highlight;
+    }
+
+    fn get_entry_text(&self) -> String {
+        self.state.borrow().entry.clone()
+    }
+}
+
+
+/// Clipboard using OSC 52 escape sequence, which works across SSH and tmux (if enabled),
+/// reading from the clipboard is not supported
+pub struct TermClipboard {
+    tty: RefCell<File>,
+}
+
+impl TermClipboard {
+    pub fn new() -> io::Result<TermClipboard> {
+        Ok(TermClipboard {
+            tty: RefCell::new(termion::get_tty()?),
+        })
+    }
+}
+
+impl Clipboard for TermClipboard {
+    fn get_text(&self) -> Option<String> { None }
+
+    fn set_text(&self, text: &str) -> Result<()> {
+        let mut tty = self.tty.borrow_mut();
+        write!(tty, "\x1b]52;c;{}\x07", base64::encode(text.as_bytes()))?;
+        tty.flush()?;
+        Ok(())
+    }
+}
+
+
+enum TermEvent {
+    Key(Key),
+    Backspace,
+    App(AppEvent),
+    /// Files changed, from `Watcher`
+    Reload(Reload),
+}
+
+/// Parse one read from the terminal, which usually contains one key
+fn parse_input(input: &[u8]) -> Vec<TermEvent> {
+    match input {
+        b"\x1b" => return vec![TermEvent::Key(Key::Escape)],
+        b"\x1b[A" | b"\x1bOA" => return vec![TermEvent::Key(Key::Up)],
+        b"\x1b[B" | b"\x1bOB" => return vec![TermEvent::Key(Key::Down)],
+        _ => {},
+    }
+    if input.starts_with(b"\x1b") {
+        trace!("Ignore escape sequence: {:?}", input);
+        return Vec::new();
+    }
+    String::from_utf8_lossy(input).chars().filter_map(|ch| {
+        match ch {
+            '\r' => Some(TermEvent::Key(Key::Enter)),
+            '\t' => Some(TermEvent::Key(Key::Tab)),
+            ' ' => Some(TermEvent::Key(Key::Space)),
+            '\n' => Some(TermEvent::Key(Key::Down)),  // Ctrl-J
+            '\x0b' => Some(TermEvent::Key(Key::Up)),  // Ctrl-K
+            '\x03' => Some(TermEvent::Key(Key::Copy)),  // Ctrl-C
+            '\x7f' | '\x08' => Some(TermEvent::Backspace),
+            ch if ch.is_control() => None,
+            ch => Some(TermEvent::Key(Key::Char(ch))),
+        }
+    }).collect()
+}
+
+fn process_term_event(app: &mut MinionsApp<TermUI>, event: TermEvent) {
+    match event {
+        TermEvent::Key(key) => {
+            let editable = app.ui.is_entry_editable();
+            let consumed = app.process_key(key);
+            if !consumed && editable {
+                match key {
+                    Key::Char(ch) => app.ui.edit_entry(Some(ch)),
+                    Key::Space => app.ui.edit_entry(Some(' ')),
+                    _ => return,
+                }
+                app.process_entry_text_changed();
+            }
+        },
+        TermEvent::Backspace => {
+            if app.ui.is_entry_editable() {
+                app.ui.edit_entry(None);
+                app.process_entry_text_changed();
+            }
+        },
+        TermEvent::App(event) => app.process_event(event),
+        // handled by `run_app`, which knows the config path
+        TermEvent::Reload(_) => {},
+    }
+}
+
+static SIGHUP_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern fn sighup_handler(_: libc::c_int) {
+    SIGHUP_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Run the app in terminal until it's hidden,
+/// reload the config from `configpath` (if any) on SIGHUP
+fn run_app<F>(build: F, configpath: Option<&std::path::Path>) -> Result<()>
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    let (send_ch, recv_ch) = mpsc::channel::<TermEvent>();
+
+    let ui = TermUI::new().chain_err(|| "Unable to open terminal")?;
+    let clipboard = TermClipboard::new().chain_err(|| "Unable to open terminal")?;
+    let notifier_ch = Mutex::new(send_ch.clone());
+    let controller = build(Box::new(clipboard), Arc::new(move |event: AppEvent| {
+        if let Err(error) = notifier_ch.lock().unwrap().send(TermEvent::App(event)) {
+            warn!("Unable to send app event: {}", error);
+        }
+    }));
+    let mut app = MinionsApp::new(ui, controller);
+    if !app.ui.is_visible() {
+        return Ok(());
+    }
+
+    let watcher = configpath.and_then(|configpath| {
+        let config = Config::new(configpath);
+        if !config.get::<bool>(&["core", "watch_files"]).unwrap() {
+            return None;
+        }
+        let watcher_ch = Mutex::new(send_ch.clone());
+        let callback = Arc::new(move |reload: Reload| {
+            let _ = watcher_ch.lock().unwrap().send(TermEvent::Reload(reload));
+        });
+        match Watcher::new(configpath, &config, callback) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                warn!("Unable to watch files: {}", error.display_chain());
+                None
+            },
+        }
+    });
+
+    if let Some(configpath) = configpath {
+        // watched files are also updated, the watcher is kept by the app
+        app.set_reload(configpath, Some(Box::new(move |_, configpath, config| {
+            if let Some(ref watcher) = watcher {
+                watcher.update(configpath, config);
+            }
+        })));
+    }
+
+    let mut tty_in = termion::get_tty().chain_err(|| "Unable to open terminal")?;
+    thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        loop {
+            match tty_in.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    for event in parse_input(&buf[..n]) {
+                        if send_ch.send(event).is_err() {
+                            return;
+                        }
+                    }
+                },
+            }
+        }
+    });
+
+    if configpath.is_some() {
+        unsafe {
+            libc::signal(libc::SIGHUP, sighup_handler as extern fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+
+    app.ui.draw()?;
+
+    loop {
+        match recv_ch.recv_timeout(Duration::from_millis(200)) {
+            Ok(TermEvent::Reload(reload)) => app.reload(&reload),
+            Ok(event) => process_term_event(&mut app, event),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if !SIGHUP_RECEIVED.swap(false, Ordering::SeqCst) {
+                    continue;
+                }
+                info!("Received SIGHUP, reloading context");
+                app.reload(&Reload::All);
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        if !app.ui.is_visible() {
+            break;
+        }
+        app.ui.draw()?;
+    }
+    Ok(())
+}
+
+/// Run the terminal frontend, until the user quits (or an item is finished)
+pub fn run(configpath: &std::path::Path) -> Result<()> {
+    let config = Config::new(configpath);
+    run_app(|clipboard, notifier| {
+        let mut controller = Controller::from_config(&config, clipboard, notifier);
+        controller.reset(false);
+        controller
+    }, Some(configpath))
+}
+
+/// Run the terminal frontend with the controller built by `build`, until it's hidden
+pub fn run_once<F>(build: F) -> Result<()>
+where F: FnOnce(Box<dyn Clipboard>, Notifier) -> Controller {
+    run_app(build, None)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_line_test() {
+        assert_eq!(single_line("hello\nworld", 20), "hello world");
+        assert_eq!(single_line("hello world", 5), "hello");
+        assert_eq!(single_line("你好 世界", 5), "你好 ");
+        assert_eq!(single_line("你好 世界", 4), "你好");
+        assert_eq!(single_line("你好 世界", 3), "你");
+    }
+}
@@ -2,20 +2,23 @@
 * @Author: BlahGeek
 * @Date:   2017-04-22
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2020-01-17
+* @Last Modified time: 2020-02-13
 */
 
 extern crate gdk_pixbuf;
 extern crate lru_cache;
-extern crate serde_json;
+extern crate glib;
 
 use std::cmp;
 use std::cell::RefCell;
 use std::path::PathBuf;
 
-use crate::mcore::item::{Item, Icon};
-use crate::mcore::context::Context;
+use std::ops::Deref;
+
+use crate::mcore::item::{Item, Icon, FA_FONTS};
 use crate::mcore::errors::Error;
+use crate::frontend::view::{View, ViewModel, EntryView, ItemView, split_highlights};
+use crate::frontend::gui::APP;
 
 use crate::frontend::gtk;
 use crate::frontend::gtk::prelude::*;
@@ -34,6 +37,17 @@ pub struct ItemUI {
     icon_text: gtk::Label,
 }
 
+/// Pango markup of text with highlighted chars in bold
+fn highlight_markup(text: &str, highlights: &[usize]) -> String {
+    split_highlights(text, highlights).into_iter().map(|(segment, highlighted)| {
+        if highlighted {
+            format!("<b>{}</b>", glib::markup_escape_text(&segment))
+        } else {
+            glib::markup_escape_text(&segment).to_string()
+        }
+    }).collect()
+}
+
 pub struct MinionsUI {
     pub window: gtk::Window,
     pub textentry: gtk::Entry,
@@ -49,11 +63,8 @@ pub struct MinionsUI {
 
     gtkbuf_cache: RefCell<LruCache<PathBuf, Option<gdk_pixbuf::Pixbuf>>>,
     items: Vec<ItemUI>,
-}
-
-lazy_static! {
-    pub static ref FA_FONTS : serde_json::Value =
-        serde_json::from_str(include_str!("./resource/fontawesome/icons.json")).unwrap();
+    /// Pending timeout to make the entry editable, see `set_entry_editable`
+    entry_editable_source: RefCell<Option<glib::SourceId>>,
 }
 
 const LISTBOX_NUM: i32 = 5;
@@ -105,14 +116,10 @@ impl MinionsUI {
             action_label: builder.get_object::<gtk::Label>("action_name").unwrap(),
             gtkbuf_cache: RefCell::new(LruCache::new(GTKBUF_CACHE_SIZE)),
             items: items,
+            entry_editable_source: RefCell::new(None),
         }
     }
 
-    pub fn set_spinning(&self, v: bool) {
-        if v { self.spinner.show(); }
-        else { self.spinner.hide(); }
-    }
-
     fn set_image_icon(&self, w_image: &gtk::Image, w_label: &gtk::Label, icon: &Icon) {
         match icon {
             &Icon::GtkName(ref ico_name) => {
@@ -177,8 +184,69 @@ impl MinionsUI {
         }
     }
 
+    fn set_action_name(&self, name: Option<&str>) {
+        if let Some(name) = name {
+            self.action_label.set_text(name);
+            self.action_box.show();
+        } else {
+            self.action_box.hide();
+        }
+    }
+
+    fn update_item(&self, idx: usize, item_view: &ItemView) {
+        let item_ui = &self.items[idx];
+        let item = &item_view.item;
+
+        if item_view.highlights.is_empty() {
+            item_ui.title.set_text(&item.title);
+        } else {
+            item_ui.title.set_markup(&highlight_markup(&item.title, &item_view.highlights));
+        }
+        if let Some(ref ico) = item.icon {
+            self.set_image_icon(&item_ui.icon, &item_ui.icon_text, ico);
+        } else {
+            self.set_image_icon(&item_ui.icon, &item_ui.icon_text, &Icon::FontAwesome("info-circle".into()) );
+        }
+
+        match item.subtitle {
+            Some(ref text) => if text.len() > 0 {
+                item_ui.subtitle.show();
+                item_ui.subtitle.set_text(&text);
+            } else {
+                item_ui.subtitle.hide();
+            },
+            None => item_ui.subtitle.hide(),
+        }
+        match item.badge {
+            Some(ref text) => { item_ui.badge.set_text(&text); item_ui.badge.show(); },
+            None => item_ui.badge.hide(),
+        }
+
+        if item_view.selectable {
+            item_ui.selectable.set_text(">");
+        } else if item_view.selectable_with_text {
+            item_ui.selectable.set_text("A");
+        } else {
+            item_ui.selectable.set_text(" ");
+        }
+    }
+
+    /// Clear the entry and make it editable immediately
+    pub fn make_entry_editable(&self) {
+        if !self.textentry.get_editable() {
+            self.textentry.set_text("");
+            self.textentry.set_editable(true);
+            self.textentry.set_can_focus(true);
+            self.textentry.grab_focus();
+        }
+    }
+
+    fn set_spinning(&self, v: bool) {
+        if v { self.spinner.show(); }
+        else { self.spinner.hide(); }
+    }
 
-    pub fn set_entry(&self, item: Option<&Item>) {
+    fn set_entry(&self, item: Option<&Item>) {
         if let Some(item) = item {
             self.textentry.set_text(&item.title);
             if let Some(ref ico) = item.icon {
@@ -190,35 +258,45 @@ impl MinionsUI {
             self.textentry.set_buffer(&gtk::EntryBuffer::new(None));
             self.set_image_icon(&self.icon, &self.icon_text, &Icon::FontAwesome("home".into()) );
         }
+        if let Some(source) = self.entry_editable_source.borrow_mut().take() {
+            glib::source_remove(source);
+        }
         self.textentry.set_can_focus(false);
         self.textentry.set_editable(false);
         self.window.set_focus::<gtk::Entry>(None);
     }
 
-    pub fn set_entry_editable(&self) {
-        if !self.textentry.get_editable() {
-            self.textentry.set_text("");
-            self.textentry.set_editable(true);
-            self.textentry.set_can_focus(true);
-            self.textentry.grab_focus();
+    fn set_entry_editable(&self) {
+        // only once when entering, instead of on every render
+        let mut source = self.entry_editable_source.borrow_mut();
+        if source.is_some() || self.textentry.get_editable() {
+            return;
         }
+        // defer make_entry_editable to prevent a leading space to be inserted
+        *source = Some(glib::timeout_add(50, move || {
+            APP.with(|app| {
+                if let Some(ref app) = *app.borrow() {
+                    app.ui.entry_editable_source.borrow_mut().take();
+                    if app.controller.is_entering() {
+                        app.ui.make_entry_editable();
+                    }
+                }
+            });
+            Continue(false)
+        }));
     }
 
-    pub fn get_entry_text(&self) -> String {
-        self.textentry.get_text().and_then(|x| Some(x.as_str().to_owned())).unwrap_or(String::new())
-    }
-
-    pub fn set_filter_text(&self, text: &str) {
+    fn set_filter_text(&self, text: &str) {
         self.filter_label.set_text(text);
     }
 
-    pub fn set_error(&self, error: &Error) {
+    fn set_error(&self, error: &Error) {
         self.reference_label.set_text(&error.display_chain().to_string());
         self.reference_label.show();
         self.set_image_icon(&self.icon, &self.icon_text, &Icon::GtkName("dialog-warning".into()));
     }
 
-    pub fn set_reference(&self, reference: Option<&String>) {
+    fn set_reference(&self, reference: Option<&String>) {
         if let Some(text) = reference {
             self.reference_label.set_text(&text);
             self.set_action_name(Some("Quicksend"));
@@ -228,16 +306,7 @@ impl MinionsUI {
         }
     }
 
-    fn set_action_name(&self, name: Option<&str>) {
-        if let Some(name) = name {
-            self.action_label.set_text(name);
-            self.action_box.show();
-        } else {
-            self.action_box.hide();
-        }
-    }
-
-    pub fn set_action(&self, item: Option<&Item>) {
+    fn set_action(&self, item: Option<&Item>) {
         if let Some(item) = item {
             if let Some(ref ico) = item.icon {
                 self.set_image_icon(&self.icon, &self.icon_text, ico);
@@ -248,40 +317,7 @@ impl MinionsUI {
         }
     }
 
-    fn update_item(&self, idx: usize, item: &Item, ctx: &Context) {
-        let item_ui = &self.items[idx];
-
-        item_ui.title.set_text(&item.title);
-        if let Some(ref ico) = item.icon {
-            self.set_image_icon(&item_ui.icon, &item_ui.icon_text, ico);
-        } else {
-            self.set_image_icon(&item_ui.icon, &item_ui.icon_text, &Icon::FontAwesome("info-circle".into()) );
-        }
-
-        match item.subtitle {
-            Some(ref text) => if text.len() > 0 {
-                item_ui.subtitle.show();
-                item_ui.subtitle.set_text(&text);
-            } else {
-                item_ui.subtitle.hide();
-            },
-            None => item_ui.subtitle.hide(),
-        }
-        match item.badge {
-            Some(ref text) => { item_ui.badge.set_text(&text); item_ui.badge.show(); },
-            None => item_ui.badge.hide(),
-        }
-
-        if ctx.selectable(&item) {
-            item_ui.selectable.set_text(">");
-        } else if ctx.selectable_with_text(&item) {
-            item_ui.selectable.set_text("A");
-        } else {
-            item_ui.selectable.set_text(" ");
-        }
-    }
-
-    pub fn set_items(&self, items: Vec<&Item>, highlight: i32, ctx: &Context) {
+    fn set_items(&self, items: &[ItemView], highlight: i32) {
 
         let mut display_start =
             if highlight < (LISTBOX_NUM / 2) { 0 }
@@ -294,7 +330,7 @@ impl MinionsUI {
 
         trace!("display: {}:{}", display_start, display_end);
         for i in display_start .. display_end {
-            self.update_item((i - display_start) as usize, items[i as usize], ctx);
+            self.update_item((i - display_start) as usize, &items[i as usize]);
             self.listbox.get_row_at_index(i - display_start).unwrap().show();
         }
         for i in (display_end - display_start) .. LISTBOX_NUM {
@@ -307,5 +343,33 @@ impl MinionsUI {
             self.listbox.select_row(self.listbox.get_row_at_index(highlight - display_start).as_ref());
         }
     }
+}
+
+impl View for MinionsUI {
+
+    fn render(&self, vm: &ViewModel) {
+        self.set_spinning(vm.spinning);
+        match vm.entry {
+            EntryView::Item(ref item) => self.set_entry(item.as_ref().map(|x| x.deref())),
+            EntryView::Editable => self.set_entry_editable(),
+        }
+        self.set_filter_text(&vm.filter_text);
+        self.set_action(vm.action.as_ref().map(|x| x.deref()));
+        self.set_reference(vm.reference.as_ref());
+        if let Some(ref error) = vm.error {
+            self.set_error(&error);
+        }
+        self.set_items(&vm.items, vm.highlight);
+
+        if vm.visible && !self.window.is_visible() {
+            self.window.show();
+        } else if !vm.visible && self.window.is_visible() {
+            self.window.hide();
+        }
+    }
+
+    fn get_entry_text(&self) -> String {
+        self.textentry.get_text().and_then(|x| Some(x.as_str().to_owned())).unwrap_or(String::new())
+    }
 
 }
@@ -0,0 +1,97 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-21
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-11
+*/
+
+use std::rc::Rc;
+
+use crate::mcore::item::Item;
+use crate::mcore::errors::Error;
+
+/// Keys handled by the app, translated from frontend-specific key events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Enter,
+    Space,
+    Escape,
+    Tab,
+    /// Down or Ctrl-J
+    Down,
+    /// Up or Ctrl-K
+    Up,
+    /// Ctrl-C
+    Copy,
+    Char(char),
+}
+
+/// Item to display in the list
+pub struct ItemView {
+    pub item: Rc<Item>,
+    /// Runnable without text input
+    pub selectable: bool,
+    /// Runnable with text input
+    pub selectable_with_text: bool,
+    /// Indices of chars in the title matched by the filter text
+    pub highlights: Vec<usize>,
+}
+
+/// Split text into segments, and whether they're highlighted
+pub fn split_highlights(text: &str, highlights: &[usize]) -> Vec<(String, bool)> {
+    let mut ret: Vec<(String, bool)> = Vec::new();
+    for (idx, ch) in text.chars().enumerate() {
+        let highlighted = highlights.contains(&idx);
+        match ret.last_mut() {
+            Some(&mut (ref mut segment, h)) if h == highlighted => segment.push(ch),
+            _ => ret.push((ch.to_string(), highlighted)),
+        }
+    }
+    ret
+}
+
+/// Content of the main entry
+pub enum EntryView {
+    /// Non-editable, showing the selected item (if any)
+    Item(Option<Rc<Item>>),
+    /// Editable, waiting for text input
+    Editable,
+}
+
+/// Everything a frontend should display, produced by `Controller`
+pub struct ViewModel {
+    pub visible: bool,
+    pub spinning: bool,
+    pub entry: EntryView,
+    pub filter_text: String,
+    /// The item waiting for text input
+    pub action: Option<Rc<Item>>,
+    /// Quicksend reference text
+    pub reference: Option<String>,
+    pub error: Option<Rc<Error>>,
+    pub items: Vec<ItemView>,
+    /// Selected index of items, or -1
+    pub highlight: i32,
+}
+
+/// The frontend (window, terminal, ...) displaying app status
+pub trait View {
+    fn render(&self, vm: &ViewModel);
+
+    /// Current text of the editable entry
+    fn get_entry_text(&self) -> String;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_highlights_test() {
+        assert_eq!(split_highlights("你好 ab", &[0, 1, 4]), vec![
+            ("你好".to_string(), true), (" a".to_string(), false), ("b".to_string(), true)]);
+        assert_eq!(split_highlights("ab", &[]), vec![("ab".to_string(), false)]);
+        assert!(split_highlights("", &[0]).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-06
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-06
+*/
+
+extern crate libc;
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::actions::{self, Provider};
+use crate::actions::plugin_manager::DISABLED_MARKER;
+use crate::mcore::config::Config;
+use crate::mcore::errors::*;
+
+/// Wait for this long without new changes before reloading
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// Interval to check whether the watcher is stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const WATCH_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ATTRIB;
+
+/// What to reload for changed files
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reload {
+    /// Config file is changed, reload everything
+    All,
+    /// Files of these providers are changed
+    Providers(Vec<Provider>),
+}
+
+/// Called from the watcher thread with what to reload
+pub type ReloadCallback = Arc<dyn Fn(Reload) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// Config files, by their names in the watched directory (None for any `.toml`, i.e. `config.d`)
+    Config(Option<Vec<PathBuf>>),
+    Provider(Provider),
+}
+
+/// Whether changing the file should be ignored, e.g. caches written by plugins themselves
+fn is_ignored(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    (name.starts_with('.') && name != DISABLED_MARKER)
+        || name.ends_with('~') || name.ends_with(".swp") || name == "__pycache__"
+}
+
+/// Parse inotify events in the buffer, return (watch descriptor, file name)
+fn parse_events(buf: &[u8]) -> Vec<(i32, PathBuf)> {
+    let header_size = std::mem::size_of::<libc::inotify_event>();
+    let mut ret = Vec::new();
+    let mut offset = 0;
+    while offset + header_size <= buf.len() {
+        let event: libc::inotify_event = unsafe {
+            std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+        };
+        let name_start = offset + header_size;
+        let name_end = std::cmp::min(name_start + event.len as usize, buf.len());
+        // name is padded with \0
+        let name: Vec<u8> = buf[name_start..name_end].iter()
+            .take_while(|x| **x != 0).cloned().collect();
+        ret.push((event.wd, PathBuf::from(std::ffi::OsString::from_vec(name))));
+        offset = name_end;
+    }
+    ret
+}
+
+/// Watch config file and files of action providers by inotify,
+/// call back (in a background thread) when they're changed
+pub struct Watcher {
+    fd: i32,
+    watches: Arc<Mutex<HashMap<i32, Target>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watcher {
+
+    pub fn new(configpath: &Path, config: &Config, callback: ReloadCallback) -> Result<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(Error::with_chain(std::io::Error::last_os_error(), "Unable to initialize inotify"));
+        }
+        let watcher = Watcher {
+            fd: fd,
+            watches: Arc::new(Mutex::new(HashMap::new())),
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+
+        let watches = watcher.watches.clone();
+        let stopped = watcher.stopped.clone();
+        thread::spawn(move || Watcher::run(fd, watches, stopped, callback));
+
+        watcher.update(configpath, config);
+        Ok(watcher)
+    }
+
+    /// Re-create watches for files in the (new) config, e.g. after plugins are added
+    pub fn update(&self, configpath: &Path, config: &Config) {
+        let mut watches = self.watches.lock().unwrap();
+        for wd in watches.keys() {
+            unsafe { libc::inotify_rm_watch(self.fd, *wd) };
+        }
+        watches.clear();
+
+        // editors may replace config files instead of writing them, so watch their directories
+        let mut config_dirs: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+        for file in std::iter::once(configpath).chain(config.files().iter().map(|x| x.as_path())) {
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let name = PathBuf::from(file.file_name().unwrap_or_default());
+            match config_dirs.iter_mut().find(|x| x.0 == dir) {
+                Some(&mut (_, ref mut names)) => names.push(name),
+                None => config_dirs.push((dir, vec![name])),
+            }
+        }
+        let mut targets: Vec<(PathBuf, Target)> = vec![(Config::dropin_dir(configpath), Target::Config(None))];
+        targets.extend(config_dirs.into_iter().map(|(dir, names)| (dir, Target::Config(Some(names)))));
+        targets.extend(actions::get_watch_paths(config).into_iter()
+                       .map(|(path, provider)| (path, Target::Provider(provider))));
+
+        for (path, target) in targets.into_iter() {
+            if !path.is_dir() {
+                continue;
+            }
+            let cpath = CString::new(path.as_os_str().as_bytes()).unwrap();
+            let wd = unsafe { libc::inotify_add_watch(self.fd, cpath.as_ptr(), WATCH_MASK) };
+            if wd < 0 {
+                warn!("Unable to watch {:?}: {}", path, std::io::Error::last_os_error());
+                continue;
+            }
+            trace!("Watching {:?} for {:?}", path, target);
+            // the config directory may also be a provider's, prefer reloading everything
+            if let Some(Target::Config(_)) = watches.get(&wd) {
+                continue;
+            }
+            watches.insert(wd, target);
+        }
+        debug!("Watching {} directories for changes", watches.len());
+    }
+
+    /// Wait until there are events (return true), or timeout
+    fn wait(fd: i32, timeout: Duration) -> bool {
+        let mut pollfd = libc::pollfd { fd: fd, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) > 0 }
+    }
+
+    /// Read pending events, return what to reload
+    fn read_events(fd: i32, watches: &Mutex<HashMap<i32, Target>>) -> Vec<Target> {
+        let mut buf = [0u8; 4096];
+        let mut ret = Vec::new();
+        loop {
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            let watches = watches.lock().unwrap();
+            for (wd, name) in parse_events(&buf[..n as usize]) {
+                match watches.get(&wd) {
+                    Some(Target::Config(None)) if name.extension().map(|x| x == "toml").unwrap_or(false) =>
+                        ret.push(Target::Config(None)),
+                    Some(Target::Config(Some(names))) if names.contains(&name) => ret.push(Target::Config(None)),
+                    Some(Target::Provider(provider)) if !is_ignored(name.as_os_str()) => {
+                        trace!("{:?} changed for {:?}", name, provider);
+                        ret.push(Target::Provider(*provider));
+                    },
+                    _ => {},
+                }
+            }
+        }
+        ret
+    }
+
+    fn run(fd: i32, watches: Arc<Mutex<HashMap<i32, Target>>>, stopped: Arc<AtomicBool>,
+           callback: ReloadCallback) {
+        while !stopped.load(Ordering::SeqCst) {
+            if !Watcher::wait(fd, POLL_INTERVAL) {
+                continue;
+            }
+            let mut targets = Watcher::read_events(fd, &watches);
+            // collect following changes, e.g. multiple files written by `git pull`
+            while Watcher::wait(fd, DEBOUNCE) {
+                targets.extend(Watcher::read_events(fd, &watches));
+            }
+            if targets.is_empty() {
+                continue;
+            }
+
+            let reload = if targets.iter().any(|x| match x { Target::Config(_) => true, _ => false }) {
+                Reload::All
+            } else {
+                let mut providers: Vec<Provider> = Vec::new();
+                for target in targets.into_iter() {
+                    if let Target::Provider(provider) = target {
+                        if !providers.contains(&provider) {
+                            providers.push(provider);
+                        }
+                    }
+                }
+                Reload::Providers(providers)
+            };
+            info!("Files changed, reloading: {:?}", reload);
+            callback(reload);
+        }
+        unsafe { libc::close(fd) };
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // the thread closes the fd
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn watcher_test() {
+        let dir = std::env::temp_dir().join(format!("minions-watcher-test-{}", std::process::id()));
+        let plugin_dir = dir.join("plugins");
+        std::fs::create_dir_all(plugin_dir.join("hello.minions")).unwrap();
+        let configpath = dir.join("config.toml");
+        std::fs::write(&configpath, format!("[core]\nextra_plugin_directories = [{:?}]\n", plugin_dir)).unwrap();
+        let config = Config::with_layers(&configpath, Some(&Config::dropin_dir(&configpath)), &[]);
+
+        let (send_ch, recv_ch) = mpsc::channel();
+        let send_ch = Mutex::new(send_ch);
+        let watcher = Watcher::new(&configpath, &config, Arc::new(move |reload| {
+            send_ch.lock().unwrap().send(reload).unwrap();
+        })).unwrap();
+
+        std::fs::write(plugin_dir.join("hello.minions").join("item.toml"), "title = \"Hello\"").unwrap();
+        std::fs::write(plugin_dir.join("hello.minions").join(".cache"), "").unwrap();
+        assert_eq!(recv_ch.recv_timeout(Duration::from_secs(5)).unwrap(),
+                   Reload::Providers(vec![Provider::CustomScript]));
+
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        std::fs::write(&configpath, "").unwrap();
+        assert_eq!(recv_ch.recv_timeout(Duration::from_secs(5)).unwrap(), Reload::All);
+
+        watcher.update(&configpath, &Config::with_layers(&configpath, Some(&Config::dropin_dir(&configpath)), &[]));
+        std::fs::write(plugin_dir.join("hello.minions").join("item.toml"), "").unwrap();
+        assert!(recv_ch.recv_timeout(Duration::from_secs(1)).is_err());
+
+        drop(watcher);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -2,15 +2,69 @@
 * @Author: BlahGeek
 * @Date:   2017-04-19
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-04-08
+* @Last Modified time: 2020-02-13
 */
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use crate::mcore::item::Item;
 use crate::mcore::errors::Result;
 
 pub type ActionResult = Result<Vec<Item>>;
 
+/// Where the action is run from, passed to `run_*_cancellable` and `run_*_streaming`
+/// for actions interested in it (e.g. scripts)
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct RunContext {
+    /// Quicksend reference, if any
+    pub reference: Option<String>,
+    /// Filter text used to select the item, if any
+    pub filter_text: Option<String>,
+}
+
+/// Token to cancel a running action, shared between the caller and the worker thread
+/// Long-running actions should check it periodically and return early once cancelled
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Token that is cancelled automatically after `timeout`
+    pub fn with_timeout(timeout: Option<Duration>) -> CancelToken {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: timeout.map(|t| Instant::now() + t),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        self.deadline.map(|d| Instant::now() >= d).unwrap_or(false)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.is_timed_out()
+    }
+
+    /// Time left before the deadline, if any
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|d| {
+            let now = Instant::now();
+            if now >= d { Duration::from_secs(0) } else { d - now }
+        })
+    }
+}
+
 /// The general action type
 pub trait Action {
 
@@ -32,6 +86,13 @@ pub trait Action {
     /// Scope of entered argument history, only valid if runnable_arg
     fn suggest_arg_scope(&self) -> Option<&str> { None }
 
+    /// Timeout of running this action (including realtime), overriding the global ones if set
+    fn timeout(&self) -> Option<Duration> { None }
+
+    /// If set, selecting this action performs the builtin action by the frontend,
+    /// instead of `run_bare` in background
+    fn builtin(&self) -> Option<BuiltinAction> { None }
+
     /// Run realtime (auto-complete)
     fn run_arg_realtime(&self, _: &str) -> ActionResult { unimplemented!() }
 
@@ -41,6 +102,56 @@ pub trait Action {
     /// Run the action with text input
     fn run_arg(&self, _: &str) -> ActionResult { unimplemented!() }
 
+    /// Cancellable versions of `run_*` with the context of this run, called by the core context
+    /// Default to ignore both, override them if the action may take long or needs the context
+    fn run_arg_realtime_cancellable(&self, text: &str, _: &CancelToken, _: &RunContext) -> ActionResult {
+        self.run_arg_realtime(text)
+    }
+
+    fn run_bare_cancellable(&self, _: &CancelToken, _: &RunContext) -> ActionResult {
+        self.run_bare()
+    }
+
+    fn run_arg_cancellable(&self, text: &str, _: &CancelToken, _: &RunContext) -> ActionResult {
+        self.run_arg(text)
+    }
+
+    /// Streaming versions of `run_*_cancellable`, called by the core context
+    /// Items may be sent in batches via `sink` as soon as they're ready,
+    /// the returned items are the last batch
+    fn run_bare_streaming(&self, _sink: &dyn Fn(Vec<Item>), token: &CancelToken,
+                          context: &RunContext) -> ActionResult {
+        self.run_bare_cancellable(token, context)
+    }
+
+    fn run_arg_streaming(&self, text: &str, _sink: &dyn Fn(Vec<Item>), token: &CancelToken,
+                         context: &RunContext) -> ActionResult {
+        self.run_arg_cancellable(text, token, context)
+    }
+
+}
+
+/// Operations on the app itself, which must be performed in the UI thread
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuiltinAction {
+    /// Copy text to clipboard, and hide the window
+    Copy(String),
+    /// Quicksend text to other actions
+    Quicksend(String),
+    /// Type text into the previously focused window, and hide the window
+    Type(String),
+    /// Reload config and all actions, which is done by the frontend
+    Reload,
+}
+
+impl Action for BuiltinAction {
+    fn runnable_bare(&self) -> bool { true }
+
+    fn builtin(&self) -> Option<BuiltinAction> { Some(self.clone()) }
+
+    fn run_bare(&self) -> ActionResult {
+        bail!("Builtin action {:?} is not supported here", self)
+    }
 }
 
 /// An actiton with arg
@@ -64,10 +175,22 @@ impl Action for PartialAction {
     fn runnable_bare(&self) -> bool { true }
 
     fn run_bare(&self) -> ActionResult {
+        self.run_bare_cancellable(&CancelToken::new(), &RunContext::default())
+    }
+
+    fn run_bare_cancellable(&self, token: &CancelToken, context: &RunContext) -> ActionResult {
+        if let Some(ref f) = self.run_callback {
+            f();
+        }
+        self.action.run_arg_cancellable(&self.arg, token, context)
+    }
+
+    fn run_bare_streaming(&self, sink: &dyn Fn(Vec<Item>), token: &CancelToken,
+                          context: &RunContext) -> ActionResult {
         if let Some(ref f) = self.run_callback {
             f();
         }
-        self.action.run_arg(&self.arg)
+        self.action.run_arg_streaming(&self.arg, sink, token, context)
     }
 
 }
@@ -0,0 +1,67 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-01-20
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-01-20
+*/
+
+use std::cell::RefCell;
+
+use crate::mcore::errors::*;
+
+/// Clipboard access used by the core context
+///
+/// Frontends provide their own implementation (e.g. GTK clipboard),
+/// headless users and tests may use `NullClipboard` or `MemoryClipboard`
+pub trait Clipboard {
+    /// Get text to quicksend, e.g. current selection or clipboard content
+    fn get_text(&self) -> Option<String>;
+
+    /// Set clipboard content
+    fn set_text(&self, text: &str) -> Result<()>;
+}
+
+/// Clipboard that is always empty and refuses to be written
+pub struct NullClipboard {}
+
+impl Clipboard for NullClipboard {
+    fn get_text(&self) -> Option<String> { None }
+
+    fn set_text(&self, _: &str) -> Result<()> {
+        bail!("No clipboard available")
+    }
+}
+
+/// Clipboard that only lives in memory
+#[derive(Default)]
+pub struct MemoryClipboard {
+    content: RefCell<Option<String>>,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&self) -> Option<String> {
+        self.content.borrow().clone()
+    }
+
+    fn set_text(&self, text: &str) -> Result<()> {
+        *self.content.borrow_mut() = Some(text.into());
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_clipboard_test() {
+        let clipboard = MemoryClipboard::default();
+        assert_eq!(clipboard.get_text(), None);
+        clipboard.set_text("hello").unwrap();
+        assert_eq!(clipboard.get_text(), Some("hello".into()));
+
+        assert!(NullClipboard{}.set_text("hello").is_err());
+        assert_eq!(NullClipboard{}.get_text(), None);
+    }
+}
@@ -10,20 +10,32 @@ use std::fmt;
 use std::io::prelude::*;
 use std::error::Error;
 
+/// Sections whose keys are not defined in default config, e.g. options of each plugin
+const FREEFORM_SECTIONS: &[&str] = &["plugins"];
+/// Prefix of environment variables overriding config, e.g. `MINIONS_CORE__HISTORY`
+const ENV_PREFIX: &str = "MINIONS_";
+/// Max depth of nested `include`s
+const MAX_INCLUDE_DEPTH: usize = 8;
+
 #[derive(Debug)]
 pub struct ConfigGetError {
     path: Vec<String>,
+    /// Why the value cannot be converted, None if not found
+    reason: Option<String>,
 }
 
 impl Error for ConfigGetError {
     fn description(&self) -> &str {
-        "Config not found"
+        if self.reason.is_some() { "Invalid config" } else { "Config not found" }
     }
 }
 
 impl fmt::Display for ConfigGetError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Config not found: {}", self.path.join(":"))
+        match self.reason {
+            Some(ref reason) => write!(f, "Invalid config {}: {}", self.path.join(":"), reason),
+            None => write!(f, "Config not found: {}", self.path.join(":")),
+        }
     }
 }
 
@@ -42,15 +54,12 @@ impl<'a> ConfigValue<'a> {
 
     fn into_result<'de, T>(self) -> Result<T, ConfigGetError>
             where T: Deserialize<'de> {
-        let err = ConfigGetError{path: self.path};
-        if let Some(v) = self.value {
-            if let Ok(v) = v.clone().try_into::<T>() {
-                Ok(v)
-            } else {
-                Err(err)
-            }
-        } else {
-            Err(err)
+        match self.value {
+            Some(v) => v.clone().try_into::<T>().map_err(|e| ConfigGetError {
+                path: self.path,
+                reason: Some(e.to_string()),
+            }),
+            None => Err(ConfigGetError { path: self.path, reason: None }),
         }
     }
 
@@ -59,35 +68,398 @@ impl<'a> ConfigValue<'a> {
         if let Some(v) = self.value {
             if let Some(m) = v.as_table() {
                 self.value = m.get(s);
+            } else {
+                self.value = None;
+            }
+        }
+    }
+}
+
+fn type_name(value: &toml::Value) -> &'static str {
+    match value {
+        toml::Value::String(_) => "string",
+        toml::Value::Integer(_) => "integer",
+        toml::Value::Float(_) => "float",
+        toml::Value::Boolean(_) => "boolean",
+        toml::Value::Datetime(_) => "datetime",
+        toml::Value::Array(_) => "array",
+        toml::Value::Table(_) => "table",
+    }
+}
+
+/// Path element of the `idx`th value in array
+fn index_key(idx: usize) -> String {
+    format!("[{}]", idx)
+}
+
+/// Readable path, e.g. `search_engine.sites[2].address`
+fn path_name(path: &[String]) -> String {
+    let mut ret = String::new();
+    for key in path.iter() {
+        if !ret.is_empty() && !key.starts_with('[') {
+            ret.push('.');
+        }
+        ret.push_str(key);
+    }
+    ret
+}
+
+/// Find position (1-based line and column) of the key in the content,
+/// by tracking table headers, e.g. `[core]` and the n-th `[[search_engine.sites]]`
+/// Values in inline arrays are found at the key of the array
+fn find_key(content: &str, path: &[String]) -> Option<(usize, usize)> {
+    let split = |text: &str| -> Vec<String> {
+        text.split('.').map(|x| x.trim().trim_matches('"').to_string()).collect()
+    };
+    let matches = |key: &[String]| {
+        path.starts_with(key) && path[key.len()..].iter().all(|x| x.starts_with('['))
+    };
+    let mut table: Vec<String> = Vec::new();
+    // count of each array of tables seen
+    let mut counts: Vec<(Vec<String>, usize)> = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let col = line.len() - trimmed.len() + 1;
+        if trimmed.starts_with('[') {
+            let is_array = trimmed.starts_with("[[");
+            let header = trimmed.trim_start_matches('[');
+            let header = &header[..header.find(']').unwrap_or(header.len())];
+            table = split(header);
+            if is_array {
+                let count = match counts.iter_mut().find(|x| x.0 == table) {
+                    Some(entry) => { entry.1 += 1; entry.1 - 1 },
+                    None => { counts.push((table.clone(), 1)); 0 },
+                };
+                table.push(index_key(count));
+            }
+            if matches(&table) {
+                return Some((idx + 1, col));
+            }
+        } else if let Some(eq) = trimmed.find('=') {
+            let mut key = table.clone();
+            key.extend(split(&trimmed[..eq]));
+            if matches(&key) {
+                return Some((idx + 1, col));
+            }
+        }
+    }
+    None
+}
+
+/// Check user config value against the default one, push problems as (path, message) to `issues`,
+/// and remove values of unknown keys and wrong types from `user`
+/// Return false if `user` has wrong type, or contains values of wrong types
+fn validate(user: &mut toml::Value, default: &toml::Value, path: &mut Vec<String>,
+            issues: &mut Vec<(Vec<String>, String)>) -> bool {
+    match (user, default) {
+        (&mut toml::Value::Table(ref mut user), &toml::Value::Table(ref default)) => {
+            if path.len() > 0 && FREEFORM_SECTIONS.contains(&path[0].as_str()) {
+                return true;
+            }
+            let mut valid = true;
+            let keys: Vec<String> = user.keys().cloned().collect();
+            for key in keys.into_iter() {
+                path.push(key.clone());
+                let keep = match default.get(&key) {
+                    Some(default_value) => {
+                        let value = user.get_mut(&key).unwrap();
+                        let value_valid = validate(value, default_value, path, issues);
+                        valid = valid && value_valid;
+                        // tables are kept, without the invalid values inside
+                        value_valid || (value.is_table() && default_value.is_table())
+                    },
+                    None if path.len() == 1 && FREEFORM_SECTIONS.contains(&key.as_str()) => true,
+                    None => {
+                        issues.push((path.clone(), format!("unknown key `{}`", path_name(path))));
+                        false
+                    },
+                };
+                if !keep {
+                    user.remove(&key);
+                }
+                path.pop();
+            }
+            valid
+        },
+        (&mut toml::Value::Array(ref mut user), &toml::Value::Array(ref default)) => {
+            if default.is_empty() {
+                return true;
+            }
+            // tables in array (e.g. search engine sites) may have any key of the default ones
+            let expected = match default[0] {
+                toml::Value::Table(_) => {
+                    let mut merged = toml::value::Table::new();
+                    for value in default.iter() {
+                        if let toml::Value::Table(ref table) = value {
+                            merged.extend(table.clone());
+                        }
+                    }
+                    // see `remove_disabled`
+                    merged.insert("disabled".into(), toml::Value::Boolean(false));
+                    toml::Value::Table(merged)
+                },
+                ref value => value.clone(),
+            };
+            // invalid entries are removed as a whole (e.g. search engine site with invalid address),
+            // keeping the others
+            let values = std::mem::replace(user, Vec::new());
+            for (idx, mut value) in values.into_iter().enumerate() {
+                path.push(index_key(idx));
+                if validate(&mut value, &expected, path, issues) {
+                    user.push(value);
+                }
+                path.pop();
+            }
+            true
+        },
+        (&mut toml::Value::Integer(_), &toml::Value::Float(_)) => true,
+        (user, default) => {
+            if type_name(user) != type_name(default) {
+                issues.push((path.clone(), format!("`{}` should be {}, found {}",
+                                                   path_name(path), type_name(default), type_name(user))));
+                false
+            } else {
+                true
+            }
+        },
+    }
+}
+
+/// Merge `overlay` into `base`: tables are merged recursively,
+/// arrays of tables with `name` are merged by name, other values are replaced
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    let named = |values: &[toml::Value]| {
+        values.iter().all(|x| x.get("name").and_then(|name| name.as_str()).is_some())
+    };
+    match (base, overlay) {
+        (&mut toml::Value::Table(ref mut base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay.into_iter() {
+                if let Some(base_value) = base.get_mut(&key) {
+                    merge(base_value, value);
+                    continue;
+                }
+                base.insert(key, value);
+            }
+        },
+        (&mut toml::Value::Array(ref mut base), toml::Value::Array(overlay)) => {
+            if !named(base.as_slice()) || overlay.is_empty() || !named(&overlay) {
+                *base = overlay;
+                return;
+            }
+            for value in overlay.into_iter() {
+                let name = value.get("name").cloned();
+                match base.iter_mut().find(|x| x.get("name") == name.as_ref()) {
+                    Some(base_value) => merge(base_value, value),
+                    None => base.push(value),
+                }
             }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Remove entries with `disabled = true` in arrays of tables,
+/// so that entries from previous layers (e.g. default search engines) can be removed by name
+fn remove_disabled(value: &mut toml::Value) {
+    match value {
+        &mut toml::Value::Table(ref mut table) => {
+            for (_, value) in table.iter_mut() {
+                remove_disabled(value);
+            }
+        },
+        &mut toml::Value::Array(ref mut values) => {
+            values.retain(|x| x.get("disabled").and_then(|x| x.as_bool()) != Some(true));
+        },
+        _ => {},
+    }
+}
+
+/// Expand `~/`, and resolve relative path in `dir`
+fn resolve_path(path: &str, dir: &std::path::Path) -> std::path::PathBuf {
+    if path.starts_with("~/") {
+        if let Some(homedir) = dirs::home_dir() {
+            return homedir.join(&path[2..]);
         }
     }
+    dir.join(path)
+}
+
+/// Config layer from environment variable, e.g. `MINIONS_CORE__HISTORY=20` for `core.history`
+/// Return None if it's not a config variable
+fn env_layer(name: &str, value: &str, default: &toml::Value) -> Option<(Vec<String>, toml::Value)> {
+    if !name.starts_with(ENV_PREFIX) || !name.contains("__") {
+        return None;
+    }
+    let path: Vec<String> = name[ENV_PREFIX.len()..].split("__").map(|x| x.to_lowercase()).collect();
+    let expected = path.iter().fold(Some(default), |value, key| value.and_then(|x| x.get(key.as_str())));
+    let mut layer = match expected {
+        Some(&toml::Value::String(_)) => toml::Value::String(value.into()),
+        // integer, boolean, array, ...
+        _ => format!("value = {}", value).parse::<toml::Value>().ok()
+            .and_then(|mut x| x.as_table_mut().and_then(|x| x.remove("value")))
+            .unwrap_or_else(|| toml::Value::String(value.into())),
+    };
+    for key in path.iter().rev() {
+        let mut table = toml::value::Table::new();
+        table.insert(key.clone(), layer);
+        layer = toml::Value::Table(table);
+    }
+    Some((path, layer))
 }
 
 #[derive(Clone)]
 pub struct Config {
     default: toml::Value,
+    /// Default config merged with all user layers
     user: Option<toml::Value>,
+    /// Problems of the user config files, e.g. syntax error, unknown key or wrong type
+    errors: Vec<String>,
+    /// User config files (and included ones) read, to watch for changes
+    files: Vec<std::path::PathBuf>,
 }
 
 impl Config {
+    /// Read config from file, with its includes, drop-in files in `config.d` and environment variables
+    /// Files with syntax error are ignored, values of unknown keys and wrong types are ignored,
+    /// and these problems are recorded
     pub fn new(p: &std::path::Path) -> Config {
+        let envs: Vec<(String, String)> = std::env::vars().collect();
+        Config::with_layers(p, Some(&Config::dropin_dir(p)), &envs)
+    }
+
+    /// Same as `new`, but read drop-in files in `dropin_dir` (if any)
+    /// and config from `envs` instead of the process's environment variables
+    pub fn with_layers(p: &std::path::Path, dropin_dir: Option<&std::path::Path>,
+                       envs: &[(String, String)]) -> Config {
         info!("Reading config from {:?}", p);
-        let mut content = String::new();
-        let fin = std::fs::File::open(p);
-        if let Ok(mut fin) = fin {
-            let _ = fin.read_to_string(&mut content);
-        }
-        let userconfig = content.parse::<toml::Value>().ok();
-        let defaultconfig = 
+        let defaultconfig =
             include_str!("../../config/default.toml")
             .parse::<toml::Value>().unwrap();
-        Config {
-            default: defaultconfig,
-            user: userconfig,
+        let mut config = Config {
+            default: defaultconfig.clone(),
+            user: Some(defaultconfig),
+            errors: Vec::new(),
+            files: Vec::new(),
+        };
+
+        config.load_file(p, 0);
+        if let Some(Ok(entries)) = dropin_dir.map(|x| x.read_dir()) {
+            let mut dropins: Vec<std::path::PathBuf> = entries
+                .filter_map(|x| x.ok())
+                .map(|x| x.path())
+                .filter(|x| x.extension().map(|ext| ext == "toml").unwrap_or(false))
+                .collect();
+            dropins.sort();
+            for dropin in dropins.iter() {
+                config.load_file(dropin, 0);
+            }
+        }
+
+        let mut envs = envs.to_vec();
+        envs.sort();
+        for (name, value) in envs.iter() {
+            if let Some((path, mut layer)) = env_layer(name, value, &config.default) {
+                info!("Overriding config {} by environment variable {}", path.join("."), name);
+                let mut issues = Vec::new();
+                validate(&mut layer, &config.default, &mut Vec::new(), &mut issues);
+                for (_, message) in issues.into_iter() {
+                    config.errors.push(format!("environment variable {}: {}", name, message));
+                }
+                merge(config.user.as_mut().unwrap(), layer);
+            }
+        }
+
+        remove_disabled(config.user.as_mut().unwrap());
+        for error in config.errors.iter() {
+            warn!("Invalid config: {}", error);
+        }
+        config
+    }
+
+    /// Directory of drop-in config files for config file `p`, e.g. `~/.minions/config.d/`
+    pub fn dropin_dir(p: &std::path::Path) -> std::path::PathBuf {
+        p.parent().unwrap_or(std::path::Path::new("")).join("config.d")
+    }
+
+    /// Read config file and its includes (before itself, so they can be overridden), merge into `user`
+    fn load_file(&mut self, p: &std::path::Path, depth: usize) {
+        if self.files.iter().any(|x| x == p) {
+            self.errors.push(format!("{}: included more than once", p.display()));
+            return;
+        }
+        self.files.push(p.to_path_buf());
+
+        let mut content = String::new();
+        match std::fs::File::open(p) {
+            Ok(mut fin) => {
+                if let Err(e) = fin.read_to_string(&mut content) {
+                    self.errors.push(format!("Unable to read config file {:?}: {}", p, e));
+                    return;
+                }
+            },
+            // only the main config file is optional
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound && depth == 0 => return,
+            Err(e) => {
+                self.errors.push(format!("Unable to read config file {:?}: {}", p, e));
+                return;
+            },
+        }
+
+        let mut value = match content.parse::<toml::Value>() {
+            Ok(v) => v,
+            Err(e) => {
+                self.errors.push(match e.line_col() {
+                    Some((line, col)) => format!("{}:{}:{}: {}, whole file ignored",
+                                                 p.display(), line + 1, col + 1, e),
+                    None => format!("{}: {}, whole file ignored", p.display(), e),
+                });
+                return;
+            },
+        };
+
+        let mut issues = Vec::new();
+        validate(&mut value, &self.default, &mut Vec::new(), &mut issues);
+        for (path, message) in issues.into_iter() {
+            self.errors.push(match find_key(&content, &path) {
+                Some((line, col)) => format!("{}:{}:{}: {}", p.display(), line, col, message),
+                None => format!("{}: {}", p.display(), message),
+            });
+        }
+
+        let includes = value.get("include").and_then(|x| x.as_array()).cloned().unwrap_or_default();
+        for include in includes.iter().filter_map(|x| x.as_str()) {
+            if depth >= MAX_INCLUDE_DEPTH {
+                self.errors.push(format!("{}: too many nested includes", p.display()));
+                break;
+            }
+            let include = resolve_path(include, p.parent().unwrap_or(std::path::Path::new("")));
+            debug!("Including config file {:?}", include);
+            self.load_file(&include, depth + 1);
+        }
+
+        merge(self.user.as_mut().unwrap(), value);
+    }
+
+    /// User config files read (or tried), including the main one and included ones
+    pub fn files(&self) -> &[std::path::PathBuf] {
+        &self.files
+    }
+
+    /// Problems of reading user config file, empty if it's valid (or not exists)
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// All problems of user config file in one message, if any
+    pub fn error(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(self.errors.join("\n"))
         }
     }
 
+    /// Get value from user config, or default config if it's not found (or has wrong type)
     pub fn get<'de, T>(&self, path: &[&str]) -> Result<T, ConfigGetError>
             where T: Deserialize<'de> {
         let mut userval = ConfigValue::new(self.user.as_ref());
@@ -96,7 +468,12 @@ impl Config {
             userval.into_next(p);
             defaultval.into_next(p);
         }
-        userval.into_result::<T>().or(defaultval.into_result::<T>())
+        match userval.into_result::<T>() {
+            Ok(v) => Ok(v),
+            Err(usererr) => defaultval.into_result::<T>().map_err(|defaulterr| {
+                if usererr.reason.is_some() { usererr } else { defaulterr }
+            }),
+        }
     }
 
     /// Same as get::<PathBuf>, but handle paths starting with `~/`
@@ -122,6 +499,8 @@ impl Config {
         Ok(Config {
             default: defaultval.into_result::<toml::Value>()?,
             user: userval.into_result::<toml::Value>().ok(),
+            errors: self.errors.clone(),
+            files: self.files.clone(),
         })
     }
 }
@@ -134,7 +513,7 @@ mod tests {
 
     #[test]
     fn test_config_get() {
-        let dummyconfig = Config::new(std::path::Path::new(""));
+        let dummyconfig = Config::with_layers(std::path::Path::new(""), None, &[]);
 
         let v = dummyconfig.get::<i32>(&["core", "filter_timeout"]).unwrap();
         assert_eq!(v, 800);
@@ -147,5 +526,96 @@ mod tests {
 
         let v = dummyconfig.get_filename(&["core", "statistic_file"]).unwrap();
         assert!(v.to_str().unwrap().ends_with("/.minions/statistic.dat"));
+        assert!(dummyconfig.error().is_none());
+
+        let v = dummyconfig.get::<i32>(&["core", "statistic_file"]).unwrap_err();
+        assert!(v.to_string().starts_with("Invalid config core:statistic_file: "));
+    }
+
+    #[test]
+    fn test_config_error() {
+        let path = std::env::temp_dir().join(format!("minions-config-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[core]\nfilter_timeout = \n").unwrap();
+        let config = Config::with_layers(&path, None, &[]);
+        assert!(config.error().unwrap().contains(":2:"));
+        assert!(config.error().unwrap().contains("whole file ignored"));
+        assert_eq!(config.get::<i32>(&["core", "filter_timeout"]).unwrap(), 800);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_config_validate() {
+        let path = std::env::temp_dir().join(format!("minions-config-validate-{}.toml", std::process::id()));
+        std::fs::write(&path, "[core]\n    filter_timout = 100\n    history = \"10\"\n    realtime_debounce = 10\n\
+                               [plugins.sdcv]\n    dictionary = \"WordNet\"\n\
+                               [[search_engine.sites]]\n    name = \"Foo\"\n    address = \"foo\"\n\
+                               [[search_engine.sites]]\n    name = \"Bar\"\n    address = 1\n").unwrap();
+        let config = Config::with_layers(&path, None, &[]);
+        let name = path.display();
+        assert_eq!(config.errors(), &[
+            format!("{}:2:5: unknown key `core.filter_timout`", name),
+            format!("{}:3:5: `core.history` should be integer, found string", name),
+            format!("{}:12:5: `search_engine.sites[1].address` should be string, found integer", name),
+        ][..]);
+        assert_eq!(config.get::<i32>(&["core", "history"]).unwrap(), 10);
+        assert_eq!(config.get::<i32>(&["core", "realtime_debounce"]).unwrap(), 10);
+        // invalid entries are ignored, keeping valid ones around them
+        let sites = config.get::<Vec<toml::Value>>(&["search_engine", "sites"]).unwrap();
+        assert!(sites.iter().any(|x| x.get("name").unwrap().as_str() == Some("Foo")));
+        assert!(!sites.iter().any(|x| x.get("name").unwrap().as_str() == Some("Bar")));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_config_layers() {
+        let dir = std::env::temp_dir().join(format!("minions-config-layers-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("config.d")).unwrap();
+        std::fs::write(dir.join("shared.toml"), "[core]\nhistory = 5\nfilter_timeout = 10\n\
+                                                 [[search_engine.sites]]\nname = \"Foo\"\naddress = \"foo\"\n").unwrap();
+        std::fs::write(dir.join("config.toml"), "include = [\"shared.toml\", \"missing.toml\"]\n\
+                                                 [core]\nhistory = 7\n\
+                                                 [[search_engine.sites]]\nname = \"Bing\"\ndisabled = true\n\
+                                                 [[search_engine.sites]]\nname = \"Google\"\naddress = \"google\"\n").unwrap();
+        std::fs::write(dir.join("config.d").join("10-timeout.toml"), "[core]\nfilter_timeout = 20\n").unwrap();
+        std::fs::write(dir.join("config.d").join("20-timeout.toml"), "[core]\nfilter_timeout = 30\n").unwrap();
+
+        let config = Config::with_layers(&dir.join("config.toml"), Some(&dir.join("config.d")),
+                                         &[("MINIONS_CORE__HISTORY".into(), "9".into()),
+                                           ("MINIONS_CORE__REALTIME_DEBOUNCE".into(), "x".into())]);
+        assert_eq!(config.errors().len(), 2);
+        assert!(config.errors()[0].contains("missing.toml"));
+        assert!(config.errors()[1].contains("MINIONS_CORE__REALTIME_DEBOUNCE"));
+        assert_eq!(config.files().len(), 5);
+        assert_eq!(config.get::<i32>(&["core", "history"]).unwrap(), 9);
+        assert_eq!(config.get::<i32>(&["core", "filter_timeout"]).unwrap(), 30);
+        // other options in the same table are kept
+        assert_eq!(config.get::<i32>(&["core", "realtime_debounce"]).unwrap(), 150);
+
+        let sites = config.get::<Vec<toml::Value>>(&["search_engine", "sites"]).unwrap();
+        let names: Vec<&str> = sites.iter().map(|x| x.get("name").unwrap().as_str().unwrap()).collect();
+        assert!(names.contains(&"Foo"));
+        assert!(!names.contains(&"Bing"));
+        let google = sites.iter().find(|x| x.get("name").unwrap().as_str() == Some("Google")).unwrap();
+        assert_eq!(google.get("address").unwrap().as_str(), Some("google"));
+        assert!(google.get("suggestion_url").is_some());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_config_env() {
+        let default = include_str!("../../config/default.toml").parse::<toml::Value>().unwrap();
+        let (path, layer) = env_layer("MINIONS_CORE__HISTORY", "20", &default).unwrap();
+        assert_eq!(path, vec!["core", "history"]);
+        assert_eq!(layer.get("core").unwrap().get("history"), Some(&toml::Value::Integer(20)));
+
+        let (_, layer) = env_layer("MINIONS_CORE__STATISTIC_FILE_SALT", "20", &default).unwrap();
+        assert_eq!(layer.get("core").unwrap().get("statistic_file_salt"), Some(&toml::Value::String("20".into())));
+
+        let (_, layer) = env_layer("MINIONS_LINUX_DESKTOP_ENTRY__DIRECTORIES", "[\"/a\"]", &default).unwrap();
+        assert_eq!(layer.get("linux_desktop_entry").unwrap().get("directories").unwrap().as_array().unwrap().len(), 1);
+
+        assert!(env_layer("MINIONS_PLUGIN_NAME", "x", &default).is_none());
+        assert!(env_layer("PATH", "x", &default).is_none());
     }
 }
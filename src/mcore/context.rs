@@ -2,92 +2,193 @@
 * @Author: BlahGeek
 * @Date:   2017-04-20
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2020-01-17
+* @Last Modified time: 2020-02-13
 */
 
-extern crate gtk;
-extern crate gdk;
-
 extern crate uuid;
 use self::uuid::Uuid;
 
-use std::sync::Arc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::rc::Rc;
-use crate::mcore::action::{ActionResult, PartialAction};
+use std::time::{Duration, Instant};
+use crate::mcore::action::{ActionResult, PartialAction, CancelToken, RunContext};
 use crate::mcore::item::Item;
 use crate::mcore::config::Config;
 use crate::mcore::lrudb::LruDB;
+use crate::mcore::clipboard::Clipboard;
 use crate::mcore::errors::*;
 use crate::actions;
+use crate::actions::Provider;
+
+/// Called by the watchdog thread once the deadline is reached
+type Expiry = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    /// Single thread calling back on timeouts of all running actions, see `watch_deadline`
+    static ref WATCHDOG: Mutex<mpsc::Sender<(Instant, Expiry)>> = {
+        let (send_ch, recv_ch) = mpsc::channel::<(Instant, Expiry)>();
+        thread::Builder::new()
+            .name("watchdog".into())
+            .spawn(move || {
+                let mut pending: Vec<(Instant, Expiry)> = Vec::new();
+                loop {
+                    let received = match pending.iter().map(|x| x.0).min() {
+                        Some(deadline) => recv_ch.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                        None => recv_ch.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                    };
+                    match received {
+                        Ok(entry) => pending.push(entry),
+                        Err(mpsc::RecvTimeoutError::Timeout) => {},
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                    let now = Instant::now();
+                    let (expired, rest): (Vec<_>, Vec<_>) = pending.into_iter().partition(|x| x.0 <= now);
+                    pending = rest;
+                    for (_, expiry) in expired.into_iter() {
+                        expiry();
+                    }
+                }
+            })
+            .unwrap();
+        Mutex::new(send_ch)
+    };
+}
 
+/// Call `expiry` in the watchdog thread once `deadline` is reached
+fn watch_deadline(deadline: Instant, expiry: Expiry) {
+    if WATCHDOG.lock().unwrap().send((deadline, expiry)).is_err() {
+        warn!("Watchdog is gone, timeout is not enforced");
+    }
+}
 
 pub struct Context {
     /// Reference data for quick-send
     pub reference: Option<String>,
+    /// Filter text used to select the running item, passed to actions by `RunContext`
+    pub filter_text: Option<String>,
     /// Candidates items list
     pub list_items: Vec<Rc<Item>>,
 
-    /// Cached all actions
-    action_items: Vec<Rc<Item>>,
+    /// Cached all actions, by their providers
+    action_items: Vec<(Provider, Vec<Rc<Item>>)>,
 
     lrudb: Arc<LruDB>,
     history_max_n: i32,
+
+    /// Timeout of selecting actions, and realtime actions
+    action_timeout: Option<Duration>,
+    realtime_timeout: Option<Duration>,
+
+    clipboard: Box<dyn Clipboard>,
 }
 
 
 impl Context {
 
     /// Create context with initial items
-    pub fn new(config: &Config) -> Context {
+    /// clipboard is provided by frontend (use `NullClipboard` for headless usage)
+    pub fn new(config: &Config, clipboard: Box<dyn Clipboard>) -> Context {
         let db_file = config.get_filename(&["core", "db_file"]).unwrap();
         let history_max_n = config.get::<i32>(&["core", "history"]).unwrap();
 
+        let mut ctx = Context::with_items(Vec::new(), LruDB::new(Some(&db_file)).unwrap(),
+                                          history_max_n, clipboard);
+        ctx.apply_config(config);
+        ctx.reload(config);
+        ctx.reset();
+        ctx
+    }
+
+    /// Create context with given action items, instead of loading actions from config
+    pub fn with_items(items: Vec<Item>, lrudb: LruDB, history_max_n: i32,
+                      clipboard: Box<dyn Clipboard>) -> Context {
         let mut ctx = Context {
             reference: None,
+            filter_text: None,
             list_items: Vec::new(),
-            action_items: Vec::new(),
-            lrudb: Arc::new(LruDB::new(Some(&db_file)).unwrap()),
+            action_items: vec![(Provider::Builtin, items.into_iter().map(|x| Rc::new(x)).collect())],
+            lrudb: Arc::new(lrudb),
             history_max_n: history_max_n,
+            action_timeout: None,
+            realtime_timeout: None,
+            clipboard: clipboard,
         };
-        ctx.reload(config);
         ctx.reset();
         ctx
     }
 
+    /// Set timeout of running actions, None for no timeout
+    pub fn set_timeouts(&mut self, action_timeout: Option<Duration>, realtime_timeout: Option<Duration>) {
+        self.action_timeout = action_timeout;
+        self.realtime_timeout = realtime_timeout;
+    }
+
+    /// Apply `[core]` options (timeouts, history and database), except action items
+    pub fn apply_config(&mut self, config: &Config) {
+        let timeout = |key: &str| match config.get::<u64>(&["core", key]).unwrap() {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        self.set_timeouts(timeout("action_timeout"), timeout("realtime_action_timeout"));
+        self.history_max_n = config.get::<i32>(&["core", "history"]).unwrap();
+
+        let db_file = config.get_filename(&["core", "db_file"]).unwrap();
+        if self.lrudb.path() != Some(db_file.as_path()) {
+            match LruDB::new(Some(&db_file)) {
+                Ok(lrudb) => self.lrudb = Arc::new(lrudb),
+                Err(error) => warn!("Unable to open database {:?}: {}", db_file, error),
+            }
+        }
+    }
+
     /// Reload all action items
     pub fn reload(&mut self, config: &Config) {
-        self.action_items = actions::get_action_items(config).into_iter()
-            .map(|x| Rc::new(x)).collect();
+        self.action_items = Provider::ALL.iter().map(|provider| {
+            (*provider, actions::get_provider_items(config, *provider).into_iter()
+                                .map(|x| Rc::new(x)).collect())
+        }).collect();
+    }
+
+    /// Reload action items of given providers, keep others untouched
+    pub fn reload_providers(&mut self, config: &Config, providers: &[Provider]) {
+        for &mut (provider, ref mut items) in self.action_items.iter_mut() {
+            if providers.contains(&provider) {
+                info!("Reloading actions of {:?}", provider);
+                *items = actions::get_provider_items(config, provider).into_iter()
+                    .map(|x| Rc::new(x)).collect();
+            }
+        }
     }
 
     /// Reset context to initial state
     pub fn reset(&mut self) {
         self.reference = None;
-        self.list_items = self.action_items.clone();
+        self.filter_text = None;
+        self.list_items = self.action_items.iter()
+            .flat_map(|&(_, ref items)| items.iter().cloned())
+            .collect();
         self.list_items.sort_by_key(|item| item.priority );
     }
 
     pub fn quicksend_from_clipboard(&mut self) -> Result<()> {
-        for clipboard in vec!["PRIMARY", "CLIPBOARD"] {
-            let clipboard = gtk::Clipboard::get(&gdk::Atom::intern(&clipboard));
-            let content = clipboard.wait_for_text();
-
-            if let Some(text) = content {
-                trace!("Clipboard content from: {:?}", text);
-                return self.quicksend(&Item {
-                    title: text.as_str().into(),
-                    ..Item::default()
-                }).chain_err(|| "Failed quicksending from clipboard");
-            }
+        if let Some(text) = self.clipboard.get_text() {
+            trace!("Clipboard content from: {:?}", text);
+            return self.quicksend(&Item {
+                title: text,
+                ..Item::default()
+            }).chain_err(|| "Failed quicksending from clipboard");
         }
         Ok(())
     }
 
     pub fn copy_content_to_clipboard(&self, item: &Item) -> Result<()> {
-        let clipboard = gtk::Clipboard::get(&gdk::Atom::intern("CLIPBOARD"));
-        clipboard.set_text(item.data.as_ref().unwrap_or(&item.title));
-        Ok(())
+        self.copy_text_to_clipboard(item.data.as_ref().unwrap_or(&item.title))
+    }
+
+    pub fn copy_text_to_clipboard(&self, text: &str) -> Result<()> {
+        self.clipboard.set_text(text)
+            .chain_err(|| "Failed copying to clipboard")
     }
 
     pub fn selectable(&self, item: &Item) -> bool {
@@ -115,34 +216,88 @@ impl Context {
         self.reference = None;
     }
 
-    pub fn async_select<F>(&self, item: &Item, callback: F) -> String
-    where F: FnOnce(ActionResult) + Send + 'static {
-        assert!(self.selectable(item));
+    /// Run `run` in a new thread with a cancel token and the current `RunContext`,
+    /// call back with its result unless cancelled
+    /// If timeout is set, call back with error on timeout (from the watchdog thread),
+    /// even if `run` ignores the token
+    fn spawn_action<R, F>(&self, timeout: Option<Duration>, run: R, callback: F) -> CancelToken
+    where R: FnOnce(&CancelToken, &RunContext) -> ActionResult + Send + 'static,
+          F: FnOnce(ActionResult) + Send + 'static {
+        let token = CancelToken::with_timeout(timeout);
+        let context = RunContext {
+            reference: self.reference.clone(),
+            filter_text: self.filter_text.clone(),
+        };
+        // taken by whichever comes first, the result or the timeout
+        let callback = Arc::new(Mutex::new(Some(callback)));
+
+        if let Some(remaining) = token.remaining() {
+            let token = token.clone();
+            let callback = callback.clone();
+            watch_deadline(Instant::now() + remaining, Box::new(move || {
+                if let Some(callback) = callback.lock().unwrap().take() {
+                    token.cancel();
+                    callback(Err("Action timed out".into()));
+                }
+            }));
+        }
+
         let thread_uuid = Uuid::new_v4().simple().to_string();
-        let action = item.action.clone().unwrap();
-        let action_arg = self.reference.clone();
+        let thread_token = token.clone();
         thread::Builder::new()
-            .name(thread_uuid.clone())
+            .name(thread_uuid)
             .spawn(move || {
-                let items =
-                    if let Some(arg) = action_arg {
-                        action.run_arg(&arg)
-                    } else {
-                        action.run_bare()
-                    };
-                debug!("async select complete, calling back");
-                callback(items.chain_err(|| "Failed selecting item"));
+                let token = thread_token;
+                let result = run(&token, &context);
+                let callback = callback.lock().unwrap().take();
+                match callback {
+                    None => debug!("Action timed out, dropping result"),
+                    Some(_) if token.is_cancelled() && !token.is_timed_out() =>
+                        debug!("Action cancelled, dropping result"),
+                    Some(callback) => callback(result),
+                }
             })
             .unwrap();
-        thread_uuid
+        token
     }
 
-    pub fn async_select_with_text<F>(&self, item: &Item, text: &str, callback: F) -> String
-    where F: FnOnce(ActionResult) + Send + 'static {
+    /// Append items (e.g. streamed from running action) to the list
+    pub fn append_items(&mut self, items: Vec<Item>) {
+        self.list_items.extend(items.into_iter().map(|x| Rc::new(x)));
+        self.list_items.sort_by_key(|x| x.priority);
+    }
+
+    /// Select the item in new thread, items streamed by the action are sent to `on_batch`,
+    /// `callback` is called with the remaining items once finished
+    pub fn async_select<B, F>(&self, item: &Item, on_batch: B, callback: F) -> CancelToken
+    where B: Fn(Vec<Item>) + Send + 'static,
+          F: FnOnce(ActionResult) + Send + 'static {
+        assert!(self.selectable(item));
+        let action = item.action.clone().unwrap();
+        let action_arg = self.reference.clone();
+        self.spawn_action(action.timeout().or(self.action_timeout), move |token, context| {
+            let sink = |items: Vec<Item>| {
+                if !token.is_cancelled() {
+                    on_batch(items);
+                }
+            };
+            if let Some(arg) = action_arg {
+                action.run_arg_streaming(&arg, &sink, token, context)
+            } else {
+                action.run_bare_streaming(&sink, token, context)
+            }
+        }, move |items| {
+            debug!("async select complete, calling back");
+            callback(items.chain_err(|| "Failed selecting item"));
+        })
+    }
+
+    pub fn async_select_with_text<B, F>(&self, item: &Item, text: &str, on_batch: B, callback: F) -> CancelToken
+    where B: Fn(Vec<Item>) + Send + 'static,
+          F: FnOnce(ActionResult) + Send + 'static {
         assert!(self.selectable_with_text(&item));
 
         let text = text.to_string();
-        let thread_uuid = Uuid::new_v4().simple().to_string();
         let action = item.action.clone().unwrap();
 
         if let Some(scope) = action.suggest_arg_scope() {
@@ -151,60 +306,60 @@ impl Context {
             }
         }
 
-        thread::Builder::new()
-            .name(thread_uuid.clone())
-            .spawn(move || {
-                let items = action.run_arg(&text);
-                debug!("async select with text complete, calling back");
-                callback(items.chain_err(|| "Failed selecting item with text"));
-            })
-            .unwrap();
-        thread_uuid
+        self.spawn_action(action.timeout().or(self.action_timeout), move |token, context| {
+            let sink = |items: Vec<Item>| {
+                if !token.is_cancelled() {
+                    on_batch(items);
+                }
+            };
+            action.run_arg_streaming(&text, &sink, token, context)
+        }, move |items| {
+            debug!("async select with text complete, calling back");
+            callback(items.chain_err(|| "Failed selecting item with text"));
+        })
     }
 
-    pub fn async_run_with_text_realtime<F>(&self, item: &Item, text: &str, callback: F) -> String
+    pub fn async_run_with_text_realtime<F>(&self, item: &Item, text: &str, callback: F) -> CancelToken
     where F: FnOnce(ActionResult) + Send + 'static {
         assert!(self.runnable_with_text_realtime(&item));
         let text = text.to_string();
-        let thread_uuid = Uuid::new_v4().simple().to_string();
         let action = item.action.clone().unwrap();
 
         let history_max_n = self.history_max_n;
         let lrudb = self.lrudb.clone();
-        thread::Builder::new()
-            .name(thread_uuid.clone())
-            .spawn(move || {
-                let items = action.run_arg_realtime(&text);
-                let scope = action.suggest_arg_scope();
-                debug!("async run with realtime text complete, calling back");
-                if action.runnable_arg_realtime_is_suggestion()
-                    && items.is_ok() && scope.is_some() {
-                    // insert partial action with lrudb
-                    let items = items.unwrap().into_iter().map(|mut item| {
-                        let data = item.title.clone();
-                        let scope : String = scope.unwrap().into();
-                        let lrudb = lrudb.clone();
-                        item.action = Some(Arc::new(PartialAction::new(
-                                        action.clone(), data.clone(),
-                                        Some(Box::new(move || {
-                                            if let Err(error) = lrudb.add(&scope, &data, history_max_n) {
-                                                warn!("Unable to save arg history: {}", error);
-                                            }
-                                        })))));
-                        Ok(item)
-                    }).collect();
-                    callback(items);
-                } else {
-                    callback(items.chain_err(|| "Failed running arg in realtime"));
-                }
-            })
-            .unwrap();
-        thread_uuid
+        let realtime_action = action.clone();
+        self.spawn_action(action.timeout().or(self.realtime_timeout), move |token, context| {
+            realtime_action.run_arg_realtime_cancellable(&text, token, context)
+        }, move |items| {
+            let scope = action.suggest_arg_scope();
+            debug!("async run with realtime text complete, calling back");
+            if action.runnable_arg_realtime_is_suggestion()
+                && items.is_ok() && scope.is_some() {
+                // insert partial action with lrudb
+                let items = items.unwrap().into_iter().map(|mut item| {
+                    let data = item.title.clone();
+                    let scope : String = scope.unwrap().into();
+                    let lrudb = lrudb.clone();
+                    item.action = Some(Arc::new(PartialAction::new(
+                                    action.clone(), data.clone(),
+                                    Some(Box::new(move || {
+                                        if let Err(error) = lrudb.add(&scope, &data, history_max_n) {
+                                            warn!("Unable to save arg history: {}", error);
+                                        }
+                                    })))));
+                    Ok(item)
+                }).collect();
+                callback(items);
+            } else {
+                callback(items.chain_err(|| "Failed running arg in realtime"));
+            }
+        })
     }
 
     pub fn quicksend(&mut self, item: &Item) -> Result<()> {
         self.list_items =
             self.action_items.iter()
+            .flat_map(|&(_, ref items)| items.iter())
             .filter(|item| {
                 item.action.as_ref().unwrap().runnable_arg()
             }).map(|x| x.clone()).collect();
@@ -2,61 +2,103 @@
 * @Author: BlahGeek
 * @Date:   2017-04-19
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-03-21
+* @Last Modified time: 2020-02-12
 */
 
 extern crate pinyin;
 
-use std::iter::Iterator;
+use std::iter::{Iterator, Enumerate, Peekable};
 use std::collections::VecDeque;
 use std::str::Chars;
 
-struct PinyinChars<'a> {
-    pyqueue: VecDeque<char>,
-    chars: Chars<'a>,
+use crate::mcore::transliteration::{Transliteration, transliterate};
+
+/// Chars of text, with non-ascii chars replaced by their transliteration,
+/// e.g. pinyin (separated by spaces), romaji, or without diacritics
+pub(crate) struct PinyinChars<'a> {
+    pyqueue: VecDeque<(usize, char)>,
+    chars: Peekable<Enumerate<Chars<'a>>>,
+    options: Transliteration,
+    /// Only the initial of each pinyin, without spaces
+    initials: bool,
 }
 
 impl<'a> Iterator for PinyinChars<'a> {
     type Item = char;
 
     fn next(&mut self) -> Option<char> {
-        if let Some(c) = self.pyqueue.pop_front() {
-            return Some(c)
+        self.next_indexed().map(|(_, c)| c)
+    }
+}
+
+impl<'a> PinyinChars<'a> {
+    pub(crate) fn new(s: &'a str) -> PinyinChars<'a> {
+        PinyinChars::with_options(s, &Transliteration::default(), false)
+    }
+
+    pub(crate) fn with_options(s: &'a str, options: &Transliteration, initials: bool) -> PinyinChars<'a> {
+        PinyinChars {
+            pyqueue: VecDeque::with_capacity(8),
+            chars: s.chars().enumerate().peekable(),
+            options: *options,
+            initials,
         }
-        if let Some(c) = self.chars.next() {
+    }
+
+    /// Next char, with the index of its original char in text
+    pub(crate) fn next_indexed(&mut self) -> Option<(usize, char)> {
+        loop {
+            if let Some(c) = self.pyqueue.pop_front() {
+                return Some(c)
+            }
+            let (idx, c) = self.chars.next()?;
             if c.is_ascii() {
-                Some(c)
-            } else {
+                return Some((idx, c));
+            }
+
+            let next = self.chars.peek().map(|x| x.1);
+            if let Some((text, consumed)) = transliterate(c, next, &self.options) {
+                if consumed {
+                    self.chars.next();
+                }
+                self.pyqueue.extend(text.chars().map(|x| (idx, x)));
+                continue;
+            }
+
+            let words = if self.options.pinyin {
                 let mut s = String::new();
                 s.push(c);
-                for word in pinyin::lazy_pinyin(&s, &pinyin::Args::new()).into_iter() {
-                    for c in word.chars() {
-                        self.pyqueue.push_back(c)
-                    }
-                    self.pyqueue.push_back(' ')
+                pinyin::lazy_pinyin(&s, &pinyin::Args::new())
+            } else {
+                Vec::new()
+            };
+            if words.is_empty() {
+                return Some((idx, c));
+            }
+            if self.initials {
+                self.pyqueue.extend(words.iter().filter_map(|word| word.chars().next()).map(|x| (idx, x)));
+                continue;
+            }
+            for word in words.into_iter() {
+                for c in word.chars() {
+                    self.pyqueue.push_back((idx, c))
                 }
-                Some(' ')
+                self.pyqueue.push_back((idx, ' '))
             }
-        } else {
-            None
+            return Some((idx, ' '));
         }
     }
 }
 
-impl<'a> PinyinChars<'a> {
-    fn new(s: &'a str) -> PinyinChars<'a> {
-        PinyinChars {
-            pyqueue: VecDeque::with_capacity(8),
-            chars: s.chars(),
-        }
-    }
+pub fn fuzzymatch(text: &str, pattern: &str, casesensitive: bool) -> i32 {
+    fuzzymatch_chars(PinyinChars::new(text), pattern, casesensitive)
 }
 
-pub fn fuzzymatch(text: &str, pattern: &str, casesensitive: bool) -> i32 {
+/// Same as `fuzzymatch`, with chars of the (transliterated) text
+pub(crate) fn fuzzymatch_chars<I: Iterator<Item = char>>(chars: I, pattern: &str, casesensitive: bool) -> i32 {
     if pattern.len() == 0 { return 0; }
 
-    let pinyin_chars = PinyinChars::new(text);
-    let mut text_iter = pinyin_chars.peekable();
+    let mut text_iter = chars.peekable();
     let mut pattern_iter = pattern.chars();
 
     let mut score = 0;
@@ -138,6 +180,22 @@ mod tests {
                    &[' ', 'n', 'i', ' ',
                    ' ', 'h', 'a', 'o', ' ',
                    ' ', 'w', 'o', 'r', 'l', 'd']);
+        let mut chars = PinyinChars::new("a你");
+        assert_eq!(std::iter::from_fn(|| chars.next_indexed()).collect::<Vec<(usize, char)>>(),
+                   &[(0, 'a'), (1, ' '), (1, 'n'), (1, 'i'), (1, ' ')]);
+        let options = Transliteration::default();
+        let mut chars = PinyinChars::with_options("中国 ok", &options, true);
+        assert_eq!(std::iter::from_fn(|| chars.next_indexed()).collect::<Vec<(usize, char)>>(),
+                   &[(0, 'z'), (1, 'g'), (2, ' '), (3, 'o'), (4, 'k')]);
+        let mut chars = PinyinChars::new("Éxtra ファイル");
+        assert_eq!(std::iter::from_fn(|| chars.next_indexed()).collect::<Vec<(usize, char)>>(),
+                   &[(0, 'E'), (1, 'x'), (2, 't'), (3, 'r'), (4, 'a'), (5, ' '),
+                     (6, 'f'), (6, 'a'), (8, 'i'), (9, 'r'), (9, 'u')]);
+        assert_eq!(PinyinChars::new("Straße Москва Αθήνα").collect::<String>(), "Strasse Moskva Athina");
+        assert_eq!(PinyinChars::new("こんにちは").collect::<String>(), "konnichiha");
+
+        let options = Transliteration { pinyin: false, diacritics: false, cyrillic: false, .. options };
+        assert_eq!(PinyinChars::with_options("你 é ж α", &options, false).collect::<String>(), "你 é ж a");
         assert!(fuzzymatch("你好 世界", "nhsj", false) > 0);
         assert!(fuzzymatch("你好 世界", "ni", false) > 0);
     }
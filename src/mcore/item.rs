@@ -5,6 +5,8 @@
 * @Last Modified time: 2020-01-16
 */
 
+extern crate serde_json;
+
 use std;
 use std::fmt;
 use std::sync::Arc;
@@ -18,6 +20,12 @@ pub enum Icon {
     FontAwesome(String),
 }
 
+lazy_static! {
+    /// FontAwesome icons metadata, used by `Icon::FontAwesome`
+    pub static ref FA_FONTS : serde_json::Value =
+        serde_json::from_str(include_str!("../frontend/resource/fontawesome/icons.json")).unwrap();
+}
+
 /// The item type (represents single selectable item (row))
 #[derive(Clone, Default)]
 pub struct Item {
@@ -4,7 +4,7 @@ extern crate rusqlite;
 use self::chrono::TimeZone;
 use self::rusqlite::params;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 pub struct LruResult {
@@ -14,6 +14,7 @@ pub struct LruResult {
 
 pub struct LruDB {
     conn: Mutex<rusqlite::Connection>,
+    path: Option<PathBuf>,
 }
 
 type Result<T> = ::std::result::Result<T, rusqlite::Error>;
@@ -76,9 +77,15 @@ impl LruDB {
 
         Ok(LruDB {
             conn: Mutex::new(conn),
+            path: dbpath.map(|x| x.to_path_buf()),
         })
     }
 
+    /// Path of the database file, None if in memory
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|x| x.as_path())
+    }
+
 }
 
 
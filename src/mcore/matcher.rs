@@ -2,27 +2,34 @@
 * @Author: BlahGeek
 * @Date:   2017-08-09
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2017-08-19
+* @Last Modified time: 2020-02-13
 */
 
 extern crate crypto;
 extern crate byteorder;
+extern crate libc;
+extern crate chrono;
 
 use self::crypto::digest::Digest;
 use self::crypto::sha1::Sha1;
 
 use self::byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use self::chrono::{TimeZone, Timelike};
 
 use std::collections::btree_map::BTreeMap;
 
 use std::io;
 use std::io::{Read, Write};
 use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::mcore::item::Item;
-use crate::mcore::fuzzymatch::fuzzymatch;
+use crate::mcore::config::Config;
+use crate::mcore::scorer::{self, Scorer, Greedy, Query};
+use crate::mcore::transliteration::Transliteration;
 
 /// 20 byte array representing SHA1 hash result
 #[derive(PartialOrd, PartialEq, Eq, Ord, Debug)]
@@ -56,60 +63,177 @@ impl<'a> From<&'a str> for SHA1Result {
 }
 
 const FILE_MAGIC: i32 = 0x23333333;
+const FILE_MAGIC_V2: i32 = 0x23333334;
 
-// Sort items using filter text, via fuzzymatch algorithm
+/// Hours of day in one time slot for `Matcher`, selections are boosted in the same slot
+const HOURS_PER_SLOT: u32 = 3;
+
+/// Current time, in seconds since epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+/// Slot of local hour of day at `time` (seconds since epoch)
+fn hour_slot(time: u64) -> u32 {
+    chrono::Local.timestamp_opt(time as i64, 0).earliest().map(|x| x.hour()).unwrap_or(0) / HOURS_PER_SLOT
+}
+
+/// Kind of quicksend reference, items selected with the same kind are boosted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Url,
+    Path,
+    Number,
+    Text,
+}
+
+impl ReferenceKind {
+    pub fn detect(text: &str) -> ReferenceKind {
+        let text = text.trim();
+        let scheme = text.find("://").map(|x| &text[..x]).unwrap_or("");
+        if (!scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)))
+            || text.starts_with("www.") {
+            ReferenceKind::Url
+        } else if !text.contains('\n') && ["/", "~/", "./", "../"].iter().any(|x| text.starts_with(x)) {
+            ReferenceKind::Path
+        } else if text.chars().any(|c| c.is_ascii_digit()) && text.replace(',', "").parse::<f64>().is_ok() {
+            ReferenceKind::Number
+        } else {
+            ReferenceKind::Text
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReferenceKind::Url => "url",
+            ReferenceKind::Path => "path",
+            ReferenceKind::Number => "number",
+            ReferenceKind::Text => "text",
+        }
+    }
+}
+
+/// Hit score which decays exponentially since `time`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Frecency {
+    score: f64,
+    time: u64,
+}
+
+impl Frecency {
+    /// Score decayed to `time`, never decays if `half_life` is None
+    fn at(&self, time: u64, half_life: Option<Duration>) -> f64 {
+        match half_life {
+            Some(half_life) if time > self.time && half_life.as_secs() > 0 =>
+                self.score * 0.5f64.powf((time - self.time) as f64 / half_life.as_secs() as f64),
+            _ => self.score,
+        }
+    }
+
+    /// Merge two records, the result is at the later time
+    fn merge(&self, other: &Frecency, half_life: Option<Duration>) -> Frecency {
+        let time = std::cmp::max(self.time, other.time);
+        Frecency {
+            score: self.at(time, half_life) + other.at(time, half_life),
+            time: time,
+        }
+    }
+}
+
+// Sort items using filter text, via the scorer (fuzzymatch algorithm by default)
 // Store select history in file to adjust future sorting
 //
 // for privacy reasons, original data should not be saved to file
-// instead, SHA1 hash and hit count is saved
+// instead, SHA1 hash and frecency (hit score and its time) is saved
 //
 // for every hit: (filter_text, selected_item), two entries is inserted:
 //   - SHA1(SALT + selected_item)
 //   - SHA1(SALT + filter_text + selected_item)
+// and for the context of the hit, if any:
+//   - SHA1(SALT + "#ref=" + kind of quicksend reference + selected_item)
+//   - SHA1(SALT + "#hour=" + slot of hour of day + selected_item), if `time_of_day` is enabled
 //
-// File format: MAGIC (SHA1, count) (SHA1, count) (SHA1, count) ...
-// At startup, the file is loaded, compacted (merge same hashes using count) and dumped back to the file
+// each hit adds 1 to the score, which halves every `half_life`,
+// so recently used items outrank those used a lot long ago
+//
+// File format: MAGIC_V2 (SHA1, score, time) (SHA1, score, time) ...
+// At startup, the file is loaded, compacted (merge same hashes by decaying to the later time) and dumped back to the file
 // While running, new data would be appended only (would be compacted on next running)
+//
+// The file may be shared by multiple processes (e.g. the GUI and dmenu), each of them holds a shared flock on it.
+// It's only compacted if no one else holds the lock, otherwise records appended by others may be lost
+//
+// Old file format: MAGIC (SHA1, count) (SHA1, count) ...
+// It's migrated on loading, with hit counts as scores at the file's modification time
 pub struct Matcher {
-    statistics: BTreeMap<SHA1Result, u32>,
+    statistics: BTreeMap<SHA1Result, Frecency>,
     salt: String,
+    half_life: Option<Duration>,
+    time_of_day: bool,
+    scorer: Box<dyn Scorer>,
+    extended_query: bool,
     file: io::BufWriter<File>,
 }
 
-fn dump(path: &Path, statistics: &BTreeMap<SHA1Result, u32>) -> io::Result<()> {
+fn dump(path: &Path, statistics: &BTreeMap<SHA1Result, Frecency>) -> io::Result<()> {
     let f = File::create(path)?;
     let mut f = io::BufWriter::new(f);
 
-    f.write_i32::<LittleEndian>(FILE_MAGIC)?;
-    for (sha1, count) in statistics.iter() {
-        sha1.write_to(&mut f)?;
-        f.write_u32::<LittleEndian>(*count)?;
+    f.write_i32::<LittleEndian>(FILE_MAGIC_V2)?;
+    for (sha1, frecency) in statistics.iter() {
+        write_record(&mut f, sha1, frecency)?;
+    }
+    Ok(())
+}
+
+/// flock the file with `operation`
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+        return Err(io::Error::last_os_error());
     }
     Ok(())
 }
 
-fn load(path: &Path) -> io::Result<BTreeMap<SHA1Result, u32>> {
+fn write_record(writer: &mut dyn Write, sha1: &SHA1Result, frecency: &Frecency) -> io::Result<()> {
+    sha1.write_to(writer)?;
+    writer.write_f64::<LittleEndian>(frecency.score)?;
+    writer.write_u64::<LittleEndian>(frecency.time)?;
+    Ok(())
+}
+
+fn load(path: &Path, half_life: Option<Duration>) -> io::Result<BTreeMap<SHA1Result, Frecency>> {
     let f = File::open(path)?;
+    let mtime = f.metadata()?.modified().ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs())
+        .unwrap_or_else(now);
     let mut f = io::BufReader::new(f);
 
     let magic = f.read_i32::<LittleEndian>()?;
-    if magic != FILE_MAGIC {
+    if magic != FILE_MAGIC && magic != FILE_MAGIC_V2 {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid file magic"));
     }
+    if magic == FILE_MAGIC {
+        info!("Migrating statistics in {:?} to the new format", path);
+    }
 
-    let mut statistics = BTreeMap::new();
+    let mut statistics: BTreeMap<SHA1Result, Frecency> = BTreeMap::new();
     loop {
         match SHA1Result::read_from(&mut f) {
             Ok(sha1) => {
-                let count = f.read_u32::<LittleEndian>()?;
-                let mut inserted = false;
+                let frecency = if magic == FILE_MAGIC {
+                    Frecency { score: f.read_u32::<LittleEndian>()? as f64, time: mtime }
+                } else {
+                    Frecency {
+                        score: f.read_f64::<LittleEndian>()?,
+                        time: f.read_u64::<LittleEndian>()?,
+                    }
+                };
                 if let Some(val) = statistics.get_mut(&sha1) {
-                    *val += count;
-                    inserted = true;
-                }
-                if !inserted {
-                    statistics.insert(sha1, count);
+                    *val = val.merge(&frecency, half_life);
+                    continue;
                 }
+                statistics.insert(sha1, frecency);
             },
             Err(_) => { break; }
         }
@@ -130,78 +254,316 @@ impl Matcher {
         s.as_str().into()
     }
 
-    fn inc(&mut self, sha1: SHA1Result) -> io::Result<u32> {
+    fn hash_context_item(&self, context: &str, item: &Item) -> SHA1Result {
+        let s = format!("{}:#{}:{}", &self.salt, context, item.get_search_str());
+        s.as_str().into()
+    }
+
+    /// Contexts of a hit with quicksend reference at `time`, and their weights in sorting
+    fn contexts(&self, reference: Option<&str>, time: u64) -> Vec<(String, i32)> {
+        let mut ret = Vec::new();
+        if let Some(reference) = reference {
+            ret.push((format!("ref={}", ReferenceKind::detect(reference).as_str()), 2));
+        }
+        if self.time_of_day {
+            ret.push((format!("hour={}", hour_slot(time)), 1));
+        }
+        ret
+    }
+
+    fn inc(&mut self, sha1: SHA1Result, time: u64) -> io::Result<f64> {
         trace!("Inc: {:?}", &sha1);
 
-        sha1.write_to(&mut self.file)?;
-        self.file.write_u32::<LittleEndian>(1)?;
+        let hit = Frecency { score: 1.0, time: time };
+        write_record(&mut self.file, &sha1, &hit)?;
         self.file.flush()?;
 
         if let Some(val) = self.statistics.get_mut(&sha1) {
-            *val += 1;
-            return Ok(*val);
+            *val = val.merge(&hit, self.half_life);
+            return Ok(val.score);
         }
-        self.statistics.insert(sha1, 1);
-        Ok(1)
+        self.statistics.insert(sha1, hit);
+        Ok(1.0)
+    }
+
+    /// Frecency score of the hash at `time`
+    fn score(&self, sha1: &SHA1Result, time: u64) -> f64 {
+        self.statistics.get(sha1).map(|x| x.at(time, self.half_life)).unwrap_or(0.0)
     }
 
-    /// Record a hit of item, optionally with pattern
+    /// Record a hit of item, optionally with pattern and quicksend reference
     /// This would update the statistics and append log to file
-    pub fn record(&mut self, pattern: Option<&str>, item: &Item) -> io::Result<()> {
+    pub fn record(&mut self, pattern: Option<&str>, reference: Option<&str>, item: &Item) -> io::Result<()> {
+        self.record_at(pattern, reference, item, now())
+    }
+
+    fn record_at(&mut self, pattern: Option<&str>, reference: Option<&str>, item: &Item,
+                 time: u64) -> io::Result<()> {
         trace!("Record history with pattern {:?} and item {}", pattern, item.get_search_str());
         let sha1 = self.hash_item(item);
-        let count = self.inc(sha1)?;
-        trace!("New count for item: {}", count);
+        let score = self.inc(sha1, time)?;
+        trace!("New score for item: {}", score);
         if let Some(pattern) = pattern {
             if pattern.len() > 0 {
                 let sha1 = self.hash_pattern_item(pattern, item);
-                let count = self.inc(sha1)?;
-                trace!("New count for item with pattern: {}", count);
+                let score = self.inc(sha1, time)?;
+                trace!("New score for item with pattern: {}", score);
             }
         }
+        for (context, _) in self.contexts(reference, time) {
+            let sha1 = self.hash_context_item(&context, item);
+            let score = self.inc(sha1, time)?;
+            trace!("New score for item with {}: {}", context, score);
+        }
         Ok(())
     }
 
-    pub fn new(path: &Path, salt: &str) -> io::Result<Matcher> {
-        let statistics = if path.exists() {
+    /// Open statistics file, scores halve every `half_life` (never decay if None)
+    pub fn new(path: &Path, salt: &str, half_life: Option<Duration>) -> io::Result<Matcher> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let exclusive = match flock(&file, libc::LOCK_EX | libc::LOCK_NB) {
+            Ok(()) => true,
+            Err(ref error) if error.raw_os_error() == Some(libc::EWOULDBLOCK) => {
+                debug!("Statistics file {:?} is shared with others", path);
+                flock(&file, libc::LOCK_SH)?;
+                false
+            },
+            Err(error) => return Err(error),
+        };
+
+        let statistics = if file.metadata()?.len() > 0 {
             debug!("Opening {:?} for statistics", path);
-            load(path)?
+            load(path, half_life)?
         } else {
             debug!("Using empty statistics");
             BTreeMap::new()
         };
         trace!("Loaded statistics: {}", statistics.len());
 
-        debug!("Dump (compact) statistics to {:?}", path);
-        dump(path, &statistics)?;
+        if exclusive {
+            debug!("Dump (compact) statistics to {:?}", path);
+            dump(path, &statistics)?;
+            flock(&file, libc::LOCK_SH)?;
+        }
 
         Ok(Matcher{
             statistics: statistics,
-            file: io::BufWriter::new(OpenOptions::new().append(true).open(path)?),
+            file: io::BufWriter::new(file),
             salt: salt.into(),
+            half_life: half_life,
+            time_of_day: false,
+            scorer: Box::new(Greedy::default()),
+            extended_query: false,
         })
     }
 
-    /// Filter and sort items using fuzzymatch
-    /// return filtered items
-    pub fn sort(&self, pattern: &str, items: &[Rc<Item>]) -> Vec<Rc<Item>> {
+    /// Open statistics file in config, `scope` (if any) separates its statistics from others
+    pub fn from_config(config: &Config, scope: Option<&str>) -> io::Result<Matcher> {
+        let global_config = config.partial(&["core"]).unwrap();
+        let salt = global_config.get::<String>(&["statistic_file_salt"]).unwrap();
+        let half_life = match global_config.get::<f64>(&["statistic_half_life_days"]).unwrap() {
+            x if x > 0.0 => Some(Duration::from_secs((x * 24.0 * 3600.0) as u64)),
+            _ => None,
+        };
+        let mut matcher = Matcher::new(
+            &global_config.get_filename(&["statistic_file"]).unwrap(),
+            &scope.map(|x| format!("{}:{}", salt, x)).unwrap_or(salt),
+            half_life)?;
+        matcher.set_time_of_day(global_config.get::<bool>(&["statistic_time_of_day"]).unwrap());
+
+        let algorithm = global_config.get::<String>(&["match_algorithm"]).unwrap();
+        let transliteration = config.get::<Transliteration>(&["transliteration"]).unwrap();
+        let scorer = scorer::get_scorer(&algorithm, &transliteration).unwrap_or_else(|| {
+            warn!("Unknown match algorithm {:?}, using fuzzy", algorithm);
+            Box::new(scorer::Fuzzy::new(&transliteration))
+        });
+        matcher.set_scorer(scorer, global_config.get::<bool>(&["match_extended_syntax"]).unwrap());
+        Ok(matcher)
+    }
+
+    /// Whether to boost items selected at the same time of day
+    pub fn set_time_of_day(&mut self, enabled: bool) {
+        self.time_of_day = enabled;
+    }
+
+    /// Set the algorithm to match items, and whether to parse the pattern in extended syntax (see `Query`)
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>, extended_query: bool) {
+        self.scorer = scorer;
+        self.extended_query = extended_query;
+    }
+
+    /// Indices of matched chars of the search str, which are in the item's title, for highlighting
+    fn title_positions(item: &Item, positions: Vec<usize>) -> Vec<usize> {
+        // search str may be extended from the title, e.g. with keywords
+        if !item.get_search_str().starts_with(&item.title) {
+            return Vec::new();
+        }
+        let title_len = item.title.chars().count();
+        positions.into_iter().filter(|x| *x < title_len).collect()
+    }
+
+    /// Filter and sort items using the scorer, boosted by history (with the quicksend reference)
+    /// return filtered items, with indices of chars in their titles matched by the pattern
+    pub fn sort(&self, pattern: &str, reference: Option<&str>, items: &[Rc<Item>]) -> Vec<(Rc<Item>, Vec<usize>)> {
+        self.sort_at(pattern, reference, items, now())
+    }
+
+    fn sort_at(&self, pattern: &str, reference: Option<&str>, items: &[Rc<Item>], time: u64)
+               -> Vec<(Rc<Item>, Vec<usize>)> {
         trace!("filter: {:?}", pattern);
-        let scores = items.iter().map(|item| {
-            let p0 = fuzzymatch(item.get_search_str(), pattern, false);
-            let p1 = ((self.statistics.get(&self.hash_item(&item)).unwrap_or(&0) + 1) as f32).log2() as i32;
-            let p2 = 2 * ((self.statistics.get(&self.hash_pattern_item(pattern, &item)).unwrap_or(&0) + 1) as f32).log2() as i32;
-            trace!("Score: {}: {} + {} + {}", &item.title, p0, p1, p2);
-            (p0 + p1 + p2, p0)  // final score and the base score
+        let query = Query::parse(pattern, self.extended_query);
+        let contexts = self.contexts(reference, time);
+        let matched = items.iter().filter_map(|item| {
+            query.match_text(item.get_search_str(), self.scorer.as_ref(), false)
+                .map(|(score, positions)| (item, score, positions))
         });
-        let mut items_and_scores =
-            items.iter().map(|x| x.clone())
-            .zip(scores.into_iter())
-            .collect::<Vec<(Rc<Item>, (i32, i32))>>();
-        items_and_scores.sort_by_key(|item_and_score| -(item_and_score.1).0);
+        let mut items_and_scores = matched.map(|(item, p0, positions)| {
+            let p1 = (self.score(&self.hash_item(&item), time) + 1.0).log2() as i32;
+            let p2 = 2 * (self.score(&self.hash_pattern_item(pattern, &item), time) + 1.0).log2() as i32;
+            let p3: i32 = contexts.iter().map(|&(ref context, weight)| {
+                weight * (self.score(&self.hash_context_item(context, &item), time) + 1.0).log2() as i32
+            }).sum();
+            trace!("Score: {}: {} + {} + {} + {}", &item.title, p0, p1, p2, p3);
+            (item.clone(), p0 + p1 + p2 + p3, Matcher::title_positions(item, positions))
+        }).collect::<Vec<(Rc<Item>, i32, Vec<usize>)>>();
+        items_and_scores.sort_by_key(|item_and_score| -item_and_score.1);
         items_and_scores.into_iter()
-            .filter(|item_and_score| (item_and_score.1).1 > 0)
-            .map(|item_and_score| item_and_score.0)
-            .collect::<Vec<Rc<Item>>>()
+            .map(|(item, _, positions)| (item, positions))
+            .collect::<Vec<(Rc<Item>, Vec<usize>)>>()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 3600;
+
+    fn item(title: &str) -> Rc<Item> {
+        Rc::new(Item { title: title.into(), .. Item::default() })
+    }
+
+    #[test]
+    fn frecency_test() {
+        let half_life = Some(Duration::from_secs(10 * DAY));
+        let old = Frecency { score: 8.0, time: 0 };
+        assert_eq!(old.at(20 * DAY, half_life), 2.0);
+        assert_eq!(old.at(20 * DAY, None), 8.0);
+        assert_eq!(old.merge(&Frecency { score: 1.0, time: 10 * DAY }, half_life),
+                   Frecency { score: 5.0, time: 10 * DAY });
+    }
+
+    #[test]
+    fn matcher_decay_test() {
+        let path = std::env::temp_dir().join(format!("minions-matcher-test-{}.dat", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // same match score, the first one wins without history
+        let items = vec![item("foo a"), item("foo b")];
+        let start = now() - 100 * DAY;
+
+        let mut matcher = Matcher::new(&path, "salt", Some(Duration::from_secs(7 * DAY))).unwrap();
+        for _ in 0..50 {
+            matcher.record_at(None, None, &items[1], start).unwrap();
+        }
+        assert_eq!(matcher.sort_at("foo", None, &items, start)[0].0.title, "foo b");
+        assert_eq!(matcher.sort_at("fb", None, &items, start)[0].1, vec![0, 4]);
+        for _ in 0..3 {
+            matcher.record_at(None, None, &items[0], start + 99 * DAY).unwrap();
+        }
+        assert_eq!(matcher.sort("foo", None, &items)[0].0.title, "foo a");
+
+        // appended records are merged on loading
+        let matcher = Matcher::new(&path, "salt", None).unwrap();
+        assert_eq!(matcher.sort("foo", None, &items)[0].0.title, "foo b");
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reference_kind_test() {
+        assert_eq!(ReferenceKind::detect("https://example.com/a"), ReferenceKind::Url);
+        assert_eq!(ReferenceKind::detect("www.example.com"), ReferenceKind::Url);
+        assert_eq!(ReferenceKind::detect("~/Downloads"), ReferenceKind::Path);
+        assert_eq!(ReferenceKind::detect(" /tmp/a b\n"), ReferenceKind::Path);
+        assert_eq!(ReferenceKind::detect("-1,234.5"), ReferenceKind::Number);
+        assert_eq!(ReferenceKind::detect("nan"), ReferenceKind::Text);
+        assert_eq!(ReferenceKind::detect("hello world"), ReferenceKind::Text);
     }
 
+    #[test]
+    fn matcher_context_test() {
+        let path = std::env::temp_dir().join(format!("minions-matcher-context-test-{}.dat", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let items = vec![item("foo a"), item("foo b")];
+        let time = now();
+
+        let mut matcher = Matcher::new(&path, "salt", None).unwrap();
+        for _ in 0..3 {
+            matcher.record_at(None, None, &items[0], time).unwrap();
+            matcher.record_at(None, Some("https://a.com"), &items[1], time).unwrap();
+        }
+        assert_eq!(matcher.sort_at("foo", None, &items, time)[0].0.title, "foo a");
+        assert_eq!(matcher.sort_at("foo", Some("/tmp"), &items, time)[0].0.title, "foo a");
+        assert_eq!(matcher.sort_at("foo", Some("http://b.com"), &items, time)[0].0.title, "foo b");
+
+        matcher.set_time_of_day(true);
+        for _ in 0..3 {
+            matcher.record_at(None, None, &items[1], time).unwrap();
+        }
+        assert_eq!(matcher.sort_at("foo", None, &items, time)[0].0.title, "foo b");
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn matcher_shared_test() {
+        let path = std::env::temp_dir().join(format!("minions-matcher-shared-test-{}.dat", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let items = vec![item("foo a")];
+
+        let mut matcher = Matcher::new(&path, "salt", None).unwrap();
+        for _ in 0..3 {
+            matcher.record(None, None, &items[0]).unwrap();
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        // not compacted while shared
+        let other = Matcher::new(&path, "salt", None).unwrap();
+        assert_eq!(other.score(&other.hash_item(&items[0]), now()), 3.0);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        matcher.record(None, None, &items[0]).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > len);
+        drop(matcher);
+        drop(other);
+
+        let matcher = Matcher::new(&path, "salt", None).unwrap();
+        assert_eq!(matcher.score(&matcher.hash_item(&items[0]), now()), 4.0);
+        assert!(std::fs::metadata(&path).unwrap().len() < len);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn matcher_migrate_test() {
+        let path = std::env::temp_dir().join(format!("minions-matcher-migrate-test-{}.dat", std::process::id()));
+        let mut f = File::create(&path).unwrap();
+        f.write_i32::<LittleEndian>(FILE_MAGIC).unwrap();
+        SHA1Result::from("salt:filezilla").write_to(&mut f).unwrap();
+        f.write_u32::<LittleEndian>(3).unwrap();
+        SHA1Result::from("salt:filezilla").write_to(&mut f).unwrap();
+        f.write_u32::<LittleEndian>(1).unwrap();
+        drop(f);
+
+        let matcher = Matcher::new(&path, "salt", None).unwrap();
+        assert_eq!(matcher.score(&"salt:filezilla".into(), now()), 4.0);
+        drop(matcher);
+
+        let mut f = File::open(&path).unwrap();
+        assert_eq!(f.read_i32::<LittleEndian>().unwrap(), FILE_MAGIC_V2);
+        let matcher = Matcher::new(&path, "salt", None).unwrap();
+        assert_eq!(matcher.score(&"salt:filezilla".into(), now()), 4.0);
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -2,14 +2,17 @@
 * @Author: BlahGeek
 * @Date:   2017-04-19
 * @Last Modified by:   BlahGeek
-* @Last Modified time: 2018-04-08
+* @Last Modified time: 2020-02-12
 */
 
 pub mod errors;
 pub mod action;
 pub mod item;
 pub mod fuzzymatch;
+pub mod scorer;
+pub mod transliteration;
 pub mod matcher;
 pub mod context;
 pub mod config;
 pub mod lrudb;
+pub mod clipboard;
@@ -0,0 +1,713 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-10
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-13
+*/
+
+use std::cell::RefCell;
+use std::cmp::max;
+
+use crate::mcore::fuzzymatch::{fuzzymatch_chars, PinyinChars};
+use crate::mcore::transliteration::Transliteration;
+
+// Scores and bonuses, same as fzf
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+const BONUS_BOUNDARY: i32 = SCORE_MATCH / 2;
+const BONUS_NON_WORD: i32 = SCORE_MATCH / 2;
+const BONUS_CAMEL123: i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+/// Max number of cells (pattern chars * text chars) to match by dynamic programming,
+/// longer texts are matched greedily, like fzf
+const MAX_DP_CELLS: usize = 16 * 1024;
+
+/// Algorithm to score how the text matches the pattern
+pub trait Scorer {
+    /// Score (higher is better, always positive) and indices of matched chars in text,
+    /// None if not matched
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)>;
+
+    /// Same as `match_text`, but the pattern is matched consecutively at the anchored position,
+    /// with the same transliteration
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)>;
+
+    /// Score of `match_text`, 0 if not matched
+    fn score(&self, text: &str, pattern: &str, casesensitive: bool) -> i32 {
+        self.match_text(text, pattern, casesensitive).map(|(score, _)| score).unwrap_or(0)
+    }
+
+    /// Indices of matched chars of `match_text`, empty if not matched
+    fn positions(&self, text: &str, pattern: &str, casesensitive: bool) -> Vec<usize> {
+        self.match_text(text, pattern, casesensitive).map(|(_, positions)| positions).unwrap_or_default()
+    }
+}
+
+/// Scorer by name in config, e.g. "fuzzy"
+pub fn get_scorer(name: &str, transliteration: &Transliteration) -> Option<Box<dyn Scorer>> {
+    match name {
+        "fuzzy" => Some(Box::new(Fuzzy::new(transliteration))),
+        "greedy" => Some(Box::new(Greedy::new(transliteration))),
+        "substring" => Some(Box::new(Substring::new(transliteration))),
+        "prefix" => Some(Box::new(Prefix::new(transliteration))),
+        "exact" => Some(Box::new(Exact::new(transliteration))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    NonWord,
+    Lower,
+    Upper,
+    Number,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Number
+    } else if c.is_alphabetic() {
+        CharClass::Lower
+    } else {
+        CharClass::NonWord
+    }
+}
+
+/// Bonus for matching each char, at word boundaries, camelCase, etc.
+fn bonuses<'a>(text: &'a [char]) -> impl Iterator<Item = i32> + 'a {
+    let mut prev = CharClass::NonWord;
+    text.iter().map(move |c| {
+        let cur = char_class(*c);
+        let bonus = match (prev, cur) {
+            (CharClass::NonWord, CharClass::NonWord) => BONUS_NON_WORD,
+            (CharClass::NonWord, _) => BONUS_BOUNDARY,
+            (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL123,
+            (CharClass::Number, CharClass::Number) => 0,
+            (_, CharClass::Number) => BONUS_CAMEL123,
+            (_, CharClass::NonWord) => BONUS_NON_WORD,
+            _ => 0,
+        };
+        prev = cur;
+        bonus
+    })
+}
+
+/// Bonus for a char following the previous matched one, whose chunk starts with `chunk_bonus`
+/// Return the bonus and the new chunk bonus
+fn consecutive_bonus(bonus: i32, chunk_bonus: i32) -> (i32, i32) {
+    if bonus >= BONUS_BOUNDARY && bonus > chunk_bonus {
+        // new chunk starts at the boundary
+        (bonus, bonus)
+    } else {
+        (max(bonus, max(BONUS_CONSECUTIVE, chunk_bonus)), chunk_bonus)
+    }
+}
+
+fn char_eq(a: char, b: char, casesensitive: bool) -> bool {
+    if casesensitive {
+        a == b
+    } else {
+        a == b || a.to_lowercase().eq(b.to_lowercase())
+    }
+}
+
+/// Pattern char matched at some position of text
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    score: i32,
+    /// Bonus of the first char in this consecutive chunk
+    chunk_bonus: i32,
+    /// Position of the previous pattern char
+    from: Option<usize>,
+}
+
+/// Buffers for `fuzzy_match`
+#[derive(Default)]
+struct DpBuffers {
+    bonuses: Vec<i32>,
+    /// cells[i * text.len() + j]: best match with pattern[i] matched at text[j]
+    cells: Vec<Option<Cell>>,
+}
+
+/// Whether all chars of pattern are in text, in order
+fn is_subsequence(text: &[char], pattern: &[char], casesensitive: bool) -> bool {
+    let mut text_iter = text.iter();
+    pattern.iter().all(|pattern_ch| text_iter.any(|ch| char_eq(*ch, *pattern_ch, casesensitive)))
+}
+
+/// Positions of pattern matched greedily, i.e. the first occurrence of each char,
+/// then shortened backwards from the last one, like fzf's v1 algorithm
+fn greedy_positions(text: &[char], pattern: &[char], casesensitive: bool) -> Option<Vec<usize>> {
+    let mut end = 0;
+    for pattern_ch in pattern.iter() {
+        end += text[end..].iter().position(|ch| char_eq(*ch, *pattern_ch, casesensitive))? + 1;
+    }
+    let mut positions = vec![0; pattern.len()];
+    for (i, pattern_ch) in pattern.iter().enumerate().rev() {
+        end = text[..end].iter().rposition(|ch| char_eq(*ch, *pattern_ch, casesensitive))?;
+        positions[i] = end;
+    }
+    Some(positions)
+}
+
+/// Score of pattern matched at the positions, same as computed in `fuzzy_match`
+fn positions_score(bonuses: &[i32], positions: &[usize]) -> i32 {
+    let mut score = 0;
+    let mut chunk_bonus = 0;
+    for (i, &pos) in positions.iter().enumerate() {
+        let bonus = bonuses[pos];
+        if i == 0 {
+            score += SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER;
+            chunk_bonus = bonus;
+        } else if pos == positions[i - 1] + 1 {
+            let (bonus, new_chunk_bonus) = consecutive_bonus(bonus, chunk_bonus);
+            chunk_bonus = new_chunk_bonus;
+            score += SCORE_MATCH + bonus;
+        } else {
+            let gap = (pos - positions[i - 1] - 1) as i32;
+            score += SCORE_GAP_START + (gap - 1) * SCORE_GAP_EXTENSION + SCORE_MATCH + bonus;
+            chunk_bonus = bonus;
+        }
+    }
+    max(score, 1)
+}
+
+/// Best score and positions of pattern matched in text (not necessarily consecutively),
+/// by dynamic programming like Smith-Waterman, or greedily if the text is too long
+fn fuzzy_match(text: &[char], pattern: &[char], casesensitive: bool, dp: &mut DpBuffers)
+               -> Option<(i32, Vec<usize>)> {
+    // most texts are not matched at all, skip them cheaply
+    if pattern.is_empty() || !is_subsequence(text, pattern, casesensitive) {
+        return None;
+    }
+    dp.bonuses.clear();
+    dp.bonuses.extend(bonuses(text));
+    if pattern.len() * text.len() > MAX_DP_CELLS {
+        let positions = greedy_positions(text, pattern, casesensitive)?;
+        return Some((positions_score(&dp.bonuses, &positions), positions));
+    }
+
+    let width = text.len();
+    let cells = &mut dp.cells;
+    cells.clear();
+    cells.resize(pattern.len() * width, None);
+    for (i, pattern_ch) in pattern.iter().enumerate() {
+        let (prev_rows, rows) = cells.split_at_mut(i * width);
+        let prev_row = &prev_rows[prev_rows.len().saturating_sub(width)..];
+        let row = &mut rows[..width];
+        // best score (and position) of the previous pattern char matched before, with gap
+        let mut gap: Option<(i32, usize)> = None;
+        for j in 0..width {
+            if i > 0 && j >= 2 {
+                let extended = gap.map(|(score, from)| (score + SCORE_GAP_EXTENSION, from));
+                let started = prev_row[j - 2].map(|cell| (cell.score + SCORE_GAP_START, j - 2));
+                gap = match (extended, started) {
+                    (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                    (a, b) => a.or(b),
+                };
+            }
+            if !char_eq(text[j], *pattern_ch, casesensitive) {
+                continue;
+            }
+            let bonus = dp.bonuses[j];
+            row[j] = if i == 0 {
+                Some(Cell { score: SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER, chunk_bonus: bonus, from: None })
+            } else {
+                let consecutive = if j >= 1 {
+                    prev_row[j - 1].map(|cell| {
+                        let (bonus, chunk_bonus) = consecutive_bonus(bonus, cell.chunk_bonus);
+                        Cell { score: cell.score + SCORE_MATCH + bonus, chunk_bonus, from: Some(j - 1) }
+                    })
+                } else {
+                    None
+                };
+                let gapped = gap.map(|(score, from)| {
+                    Cell { score: score + SCORE_MATCH + bonus, chunk_bonus: bonus, from: Some(from) }
+                });
+                match (consecutive, gapped) {
+                    (Some(a), Some(b)) => Some(if a.score >= b.score { a } else { b }),
+                    (a, b) => a.or(b),
+                }
+            };
+        }
+    }
+
+    let (mut pos, best) = cells[(pattern.len() - 1) * width ..].iter().enumerate()
+        .filter_map(|(j, cell)| cell.map(|cell| (j, cell)))
+        .max_by_key(|&(_, cell)| cell.score)?;
+    let mut positions = vec![pos];
+    for i in (1..pattern.len()).rev() {
+        pos = cells[i * width + pos].unwrap().from.unwrap();
+        positions.push(pos);
+    }
+    positions.reverse();
+    // long gaps may make the score negative, but it's still matched
+    Some((max(best.score, 1), positions))
+}
+
+/// Position to match the pattern literally, see `Scorer::match_literal`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Anywhere,
+    Start,
+    End,
+    Both,
+}
+
+/// Best score and start position of pattern matched consecutively in text, at the anchored position
+fn literal_match(text: &[char], pattern: &[char], casesensitive: bool, anchor: Anchor) -> Option<(i32, usize)> {
+    if pattern.is_empty() || pattern.len() > text.len() {
+        return None;
+    }
+    let bonuses: Vec<i32> = bonuses(text).collect();
+    let last = text.len() - pattern.len();
+    let starts: Vec<usize> = match anchor {
+        Anchor::Anywhere => (0..=last).collect(),
+        Anchor::Start => vec![0],
+        Anchor::End => vec![last],
+        Anchor::Both if last == 0 => vec![0],
+        Anchor::Both => vec![],
+    };
+    starts.into_iter()
+        .filter(|start| pattern.iter().enumerate().all(|(i, ch)| char_eq(text[start + i], *ch, casesensitive)))
+        .map(|start| {
+            let mut chunk_bonus = bonuses[start];
+            let mut score = SCORE_MATCH + chunk_bonus * BONUS_FIRST_CHAR_MULTIPLIER;
+            for bonus in bonuses[start + 1 .. start + pattern.len()].iter() {
+                let (bonus, new_chunk_bonus) = consecutive_bonus(*bonus, chunk_bonus);
+                chunk_bonus = new_chunk_bonus;
+                score += SCORE_MATCH + bonus;
+            }
+            (score, start)
+        })
+        .max_by_key(|&(score, start)| (score, -(start as i64)))
+}
+
+/// Buffers reused between texts, to avoid allocating for each item on each keystroke
+#[derive(Default)]
+struct Buffers {
+    pattern: Vec<char>,
+    chars: Vec<char>,
+    indices: Vec<usize>,
+    dp: DpBuffers,
+}
+
+/// Variants of text to match, i.e. whether with pinyin initials (see `fill_variant`)
+fn variants(text: &str, transliteration: &Transliteration) -> &'static [bool] {
+    if transliteration.pinyin && transliteration.pinyin_initials && !text.is_ascii() {
+        &[false, true]
+    } else {
+        &[false]
+    }
+}
+
+/// Chars of text to match, with transliteration (see `PinyinChars`) and pinyin initials if enabled,
+/// and indices of their original chars
+fn fill_variant(text: &str, transliteration: &Transliteration, initials: bool,
+                chars: &mut Vec<char>, indices: &mut Vec<usize>) {
+    chars.clear();
+    indices.clear();
+    if text.is_ascii() {
+        // nothing to transliterate
+        chars.extend(text.bytes().map(char::from));
+        indices.extend(0..text.len());
+        return;
+    }
+    let mut text_chars = PinyinChars::with_options(text, transliteration, initials);
+    while let Some((idx, ch)) = text_chars.next_indexed() {
+        chars.push(ch);
+        indices.push(idx);
+    }
+}
+
+/// Best score and positions (of original chars) of pattern matched by `f` in variants of text,
+/// `f` is called with chars of the text and the pattern
+fn best_variant<F>(text: &str, pattern: &str, transliteration: &Transliteration, buffers: &mut Buffers, mut f: F)
+                   -> Option<(i32, Vec<usize>)>
+    where F: FnMut(&[char], &[char], &mut DpBuffers) -> Option<(i32, Vec<usize>)> {
+    let Buffers { pattern: ref mut pattern_chars, ref mut chars, ref mut indices, ref mut dp } = *buffers;
+    pattern_chars.clear();
+    pattern_chars.extend(pattern.chars());
+    let mut best: Option<(i32, Vec<usize>)> = None;
+    for &initials in variants(text, transliteration) {
+        fill_variant(text, transliteration, initials, chars, indices);
+        if let Some((score, positions)) = f(chars, pattern_chars, dp) {
+            if best.as_ref().map(|x| score >= x.0).unwrap_or(true) {
+                best = Some((score, positions.into_iter().map(|x| indices[x]).collect()));
+            }
+        }
+    }
+    // multiple transliterated chars of the same original char
+    best.map(|(score, mut positions)| {
+        positions.dedup();
+        (score, positions)
+    })
+}
+
+/// Score and positions of `literal_match` in variants of text
+fn literal_match_variants(text: &str, pattern: &str, casesensitive: bool, anchor: Anchor,
+                          transliteration: &Transliteration, buffers: &mut Buffers) -> Option<(i32, Vec<usize>)> {
+    best_variant(text, pattern, transliteration, buffers, |chars, pattern, _| {
+        // ignore spaces around transliterated words, e.g. " zhong  guo " for "中国"
+        let (start, end) = if text.is_ascii() {
+            (0, chars.len())
+        } else {
+            let start = chars.iter().take_while(|x| **x == ' ').count();
+            (start, chars.len() - chars[start..].iter().rev().take_while(|x| **x == ' ').count())
+        };
+        literal_match(&chars[start..end], pattern, casesensitive, anchor)
+            .map(|(score, x)| (score, (start + x .. start + x + pattern.len()).collect()))
+    })
+}
+
+/// Fuzzy match finding the best alignment, like fzf's v2 algorithm
+#[derive(Default)]
+pub struct Fuzzy {
+    transliteration: Transliteration,
+    buffers: RefCell<Buffers>,
+}
+
+impl Fuzzy {
+    pub fn new(transliteration: &Transliteration) -> Fuzzy {
+        Fuzzy { transliteration: *transliteration, buffers: RefCell::default() }
+    }
+}
+
+impl Scorer for Fuzzy {
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        best_variant(text, pattern, &self.transliteration, &mut self.buffers.borrow_mut(),
+                     |chars, pattern, dp| fuzzy_match(chars, pattern, casesensitive, dp))
+    }
+
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)> {
+        literal_match_variants(text, pattern, casesensitive, anchor, &self.transliteration, &mut self.buffers.borrow_mut())
+    }
+}
+
+/// The original greedy fuzzy match, see `fuzzymatch`
+#[derive(Default)]
+pub struct Greedy {
+    transliteration: Transliteration,
+    buffers: RefCell<Buffers>,
+}
+
+impl Greedy {
+    pub fn new(transliteration: &Transliteration) -> Greedy {
+        Greedy { transliteration: *transliteration, buffers: RefCell::default() }
+    }
+}
+
+impl Scorer for Greedy {
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        best_variant(text, pattern, &self.transliteration, &mut self.buffers.borrow_mut(), |chars, pattern_chars, _| {
+            let score = fuzzymatch_chars(chars.iter().cloned(), pattern, casesensitive);
+            if score <= 0 {
+                return None;
+            }
+            // the first occurrence of each char, same as `fuzzymatch`
+            let mut positions = Vec::with_capacity(pattern_chars.len());
+            let mut text_iter = chars.iter().enumerate();
+            for pattern_ch in pattern_chars.iter() {
+                let (idx, _) = text_iter.find(|&(_, ch)| char_eq(*ch, *pattern_ch, casesensitive))?;
+                positions.push(idx);
+            }
+            Some((score, positions))
+        })
+    }
+
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)> {
+        literal_match_variants(text, pattern, casesensitive, anchor, &self.transliteration, &mut self.buffers.borrow_mut())
+    }
+}
+
+/// Text contains the pattern
+#[derive(Default)]
+pub struct Substring {
+    transliteration: Transliteration,
+    buffers: RefCell<Buffers>,
+}
+
+impl Substring {
+    pub fn new(transliteration: &Transliteration) -> Substring {
+        Substring { transliteration: *transliteration, buffers: RefCell::default() }
+    }
+}
+
+impl Scorer for Substring {
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        self.match_literal(text, pattern, casesensitive, Anchor::Anywhere)
+    }
+
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)> {
+        literal_match_variants(text, pattern, casesensitive, anchor, &self.transliteration, &mut self.buffers.borrow_mut())
+    }
+}
+
+/// Text starts with the pattern
+#[derive(Default)]
+pub struct Prefix {
+    transliteration: Transliteration,
+    buffers: RefCell<Buffers>,
+}
+
+impl Prefix {
+    pub fn new(transliteration: &Transliteration) -> Prefix {
+        Prefix { transliteration: *transliteration, buffers: RefCell::default() }
+    }
+}
+
+impl Scorer for Prefix {
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        self.match_literal(text, pattern, casesensitive, Anchor::Start)
+    }
+
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)> {
+        literal_match_variants(text, pattern, casesensitive, anchor, &self.transliteration, &mut self.buffers.borrow_mut())
+    }
+}
+
+/// Text equals to the pattern
+#[derive(Default)]
+pub struct Exact {
+    transliteration: Transliteration,
+    buffers: RefCell<Buffers>,
+}
+
+impl Exact {
+    pub fn new(transliteration: &Transliteration) -> Exact {
+        Exact { transliteration: *transliteration, buffers: RefCell::default() }
+    }
+}
+
+impl Scorer for Exact {
+    fn match_text(&self, text: &str, pattern: &str, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        self.match_literal(text, pattern, casesensitive, Anchor::Both)
+    }
+
+    fn match_literal(&self, text: &str, pattern: &str, casesensitive: bool, anchor: Anchor) -> Option<(i32, Vec<usize>)> {
+        literal_match_variants(text, pattern, casesensitive, anchor, &self.transliteration, &mut self.buffers.borrow_mut())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TermKind {
+    /// Matched by the scorer
+    Default,
+    Literal(Anchor),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    text: String,
+    kind: TermKind,
+    negate: bool,
+}
+
+/// Split by whitespaces, except escaped ones ("\ ")
+fn split_terms(pattern: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut current = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' && chars.peek() == Some(&' ') {
+            current.push(chars.next().unwrap());
+        } else if ch.is_whitespace() {
+            if !current.is_empty() {
+                ret.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(ch);
+        }
+    }
+    if !current.is_empty() {
+        ret.push(current);
+    }
+    ret
+}
+
+/// Filter pattern, optionally in fzf's extended syntax:
+/// space separated terms should all match, each can be
+/// `'substring`, `^prefix`, `suffix$`, `^exact$`, or negated by `!`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+
+    pub fn parse(pattern: &str, extended: bool) -> Query {
+        if !extended {
+            return Query {
+                terms: vec![Term { text: pattern.into(), kind: TermKind::Default, negate: false }],
+            };
+        }
+        let terms = split_terms(pattern).into_iter().map(|token| {
+            let mut text = token.as_str();
+            let negate = text.len() > 1 && text.starts_with('!');
+            if negate {
+                text = &text[1..];
+            }
+            // negated terms are not fuzzy, or it would exclude too much
+            let mut kind = if negate { TermKind::Literal(Anchor::Anywhere) } else { TermKind::Default };
+            if text.len() > 1 && text.starts_with('\'') {
+                text = &text[1..];
+                kind = TermKind::Literal(Anchor::Anywhere);
+            } else {
+                let start = text.len() > 1 && text.starts_with('^');
+                if start {
+                    text = &text[1..];
+                }
+                let end = text.len() > 1 && text.ends_with('$');
+                if end {
+                    text = &text[..text.len() - 1];
+                }
+                match (start, end) {
+                    (true, true) => kind = TermKind::Literal(Anchor::Both),
+                    (true, false) => kind = TermKind::Literal(Anchor::Start),
+                    (false, true) => kind = TermKind::Literal(Anchor::End),
+                    _ => {},
+                }
+            }
+            Term { text: text.into(), kind, negate }
+        }).collect();
+        Query { terms }
+    }
+
+    /// Total score of all terms and indices of chars matched by (not negated) terms,
+    /// None if any of them does not match
+    pub fn match_text(&self, text: &str, scorer: &dyn Scorer, casesensitive: bool) -> Option<(i32, Vec<usize>)> {
+        if self.terms.is_empty() {
+            return None;
+        }
+        let mut total = 0;
+        let mut positions = Vec::new();
+        for term in self.terms.iter() {
+            let matched = match term.kind {
+                TermKind::Default => scorer.match_text(text, &term.text, casesensitive),
+                TermKind::Literal(anchor) => scorer.match_literal(text, &term.text, casesensitive, anchor),
+            };
+            match (term.negate, matched) {
+                (false, Some((score, term_positions))) => {
+                    total += score;
+                    positions.extend(term_positions);
+                },
+                (true, None) => {},
+                _ => return None,
+            }
+        }
+        positions.sort();
+        positions.dedup();
+        // at least 1 with only negated terms
+        Some((max(total, 1), positions))
+    }
+
+    /// Total score of all terms, 0 if any of them does not match
+    pub fn score(&self, text: &str, scorer: &dyn Scorer, casesensitive: bool) -> i32 {
+        self.match_text(text, scorer, casesensitive).map(|(score, _)| score).unwrap_or(0)
+    }
+
+    /// Indices of chars in text matched by all (not negated) terms, empty if not matched
+    pub fn positions(&self, text: &str, scorer: &dyn Scorer, casesensitive: bool) -> Vec<usize> {
+        self.match_text(text, scorer, casesensitive).map(|(_, positions)| positions).unwrap_or_default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzzy(text: &str, pattern: &str) -> i32 {
+        Fuzzy::default().score(text, pattern, false)
+    }
+
+    #[test]
+    fn fuzzy_test() {
+        assert!(fuzzy("hello world", "hw") > fuzzy("hello world", "hl"));
+        assert!(fuzzy("hello world", "hell") > fuzzy("hello world", "hwld"));
+        assert_eq!(fuzzy("hello world", "hww"), 0);
+        assert_eq!(Fuzzy::default().score("Hello World", "hw", true), 0);
+        assert!(fuzzy("FooBar", "fb") > fuzzy("Foobar", "fb"));
+        assert!(fuzzy("你好 世界", "nhsj") > 0);
+        assert_eq!(fuzzy("", "hw"), 0);
+        assert!(fuzzy("Ελληνικά", "ellin") > 0);
+        let plain = Fuzzy::new(&Transliteration { pinyin_initials: false, greek: false, .. Transliteration::default() });
+        assert!(plain.score("微信 WeChat", "wx", false) < fuzzy("微信 WeChat", "wx"));
+        assert_eq!(plain.score("Ελληνικά", "ellin", false), 0);
+        assert_eq!(fuzzy("hello", ""), 0);
+        // the greedy one takes the first "a" and misses the word
+        assert!(fuzzy("a-b-c abc", "abc") > fuzzy("a-b-c", "abc"));
+        assert_eq!(Greedy::default().score("a-b-c abc", "abc", false), Greedy::default().score("a-b-c", "abc", false));
+        // long texts are matched greedily, with the same score for the same alignment
+        let long = format!("{} foo bar", "a".repeat(MAX_DP_CELLS));
+        assert_eq!(fuzzy(&long, "fb"), fuzzy("foo bar", "fb"));
+        assert_eq!(Fuzzy::default().positions(&long, "fb", false), vec![MAX_DP_CELLS + 1, MAX_DP_CELLS + 5]);
+        assert_eq!(fuzzy(&long, "fz"), 0);
+    }
+
+    #[test]
+    fn positions_test() {
+        assert_eq!(Fuzzy::default().positions("a-b-c abc", "abc", false), vec![6, 7, 8]);
+        assert_eq!(Greedy::default().positions("a-b-c abc", "abc", false), vec![0, 2, 4]);
+        assert_eq!(Fuzzy::default().positions("Hello World", "hw", false), vec![0, 6]);
+        assert_eq!(Fuzzy::default().positions("你好 世界", "nhsj", false), vec![0, 1, 3, 4]);
+        assert_eq!(Fuzzy::default().positions("你好 世界", "hw", false), Vec::<usize>::new());
+        assert_eq!(Fuzzy::default().positions("你好 世界", "nhs", false), vec![0, 1, 3]);
+        assert_eq!(Greedy::default().positions("Café", "cafe", false), vec![0, 1, 2, 3]);
+        assert_eq!(Substring::default().positions("abc abc", "bc", false), vec![1, 2]);
+        assert_eq!(Prefix::default().positions("abc", "ab", false), vec![0, 1]);
+        assert_eq!(Query::parse("fire ^moz !chrome", true).positions("Mozilla Firefox", &Fuzzy::default(), false),
+                   vec![0, 1, 2, 8, 9, 10, 11]);
+        assert!(Query::parse("fire !fox", true).positions("Mozilla Firefox", &Fuzzy::default(), false).is_empty());
+    }
+
+    #[test]
+    fn literal_test() {
+        assert!(Substring::default().score("Hello World", "o w", false) > 0);
+        assert_eq!(Substring::default().score("Hello World", "hw", false), 0);
+        assert!(Substring::default().score("Hello World", "wor", false) > Substring::default().score("Hello World", "orl", false));
+        assert!(Prefix::default().score("Hello World", "hell", false) > 0);
+        assert_eq!(Prefix::default().score("Hello World", "world", false), 0);
+        assert!(Exact::default().score("Hello", "hello", false) > 0);
+        assert_eq!(Exact::default().score("Hello World", "hello", false), 0);
+        // with transliteration, same as fuzzy
+        assert_eq!(Substring::default().positions("你好 世界", "sj", false), vec![3, 4]);
+        assert_eq!(Prefix::default().positions("中国", "zhong", false), vec![0]);
+        assert!(Exact::default().score("中国", "zg", false) > 0);
+        assert_eq!(Exact::new(&Transliteration { pinyin: false, .. Transliteration::default() })
+                   .score("中国", "zg", false), 0);
+        let scorer = Fuzzy::default();
+        assert_eq!(Query::parse("'sj", true).positions("你好 世界", &scorer, false), vec![3, 4]);
+        assert!(Query::parse("^zhong guo$", true).score("中国", &scorer, false) > 0);
+        assert_eq!(Query::parse("zg !^zhong", true).score("中国", &scorer, false), 0);
+    }
+
+    #[test]
+    fn query_test() {
+        assert_eq!(Query::parse("a b", false).terms.len(), 1);
+        assert_eq!(Query::parse(" !'a ^b c$ ^d$ e\\ f '", true).terms, vec![
+            Term { text: "a".into(), kind: TermKind::Literal(Anchor::Anywhere), negate: true },
+            Term { text: "b".into(), kind: TermKind::Literal(Anchor::Start), negate: false },
+            Term { text: "c".into(), kind: TermKind::Literal(Anchor::End), negate: false },
+            Term { text: "d".into(), kind: TermKind::Literal(Anchor::Both), negate: false },
+            Term { text: "e f".into(), kind: TermKind::Default, negate: false },
+            Term { text: "'".into(), kind: TermKind::Default, negate: false },
+        ]);
+
+        let scorer = Fuzzy::default();
+        let score = |pattern: &str| Query::parse(pattern, true).score("Mozilla Firefox", &scorer, false);
+        assert!(score("moz fire") > score("fire"));
+        assert_eq!(score("moz chrome"), 0);
+        assert_eq!(score("'mzf"), 0);
+        assert!(score("^moz fox$") > 0);
+        assert_eq!(score("^fire"), 0);
+        assert_eq!(score("fire !zilla"), 0);
+        assert!(score("!chrome") > 0);
+        assert_eq!(score(""), 0);
+    }
+}
@@ -0,0 +1,191 @@
+/*
+* @Author: BlahGeek
+* @Date:   2020-02-12
+* @Last Modified by:   BlahGeek
+* @Last Modified time: 2020-02-12
+*/
+
+/// Which non-ASCII chars are matched by their latin transliteration, see `[transliteration]` in config
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Transliteration {
+    /// Chinese characters as pinyin, e.g. "zhong guo" for "中国"
+    pub pinyin: bool,
+    /// Also match Chinese characters by pinyin initials, e.g. "zg" for "中国"
+    pub pinyin_initials: bool,
+    /// Japanese kana as romaji, e.g. "kamera" for "カメラ"
+    pub romaji: bool,
+    /// Latin letters without diacritics, e.g. "e" for "é", "ss" for "ß"
+    pub diacritics: bool,
+    /// e.g. "moskva" for "москва"
+    pub cyrillic: bool,
+    /// e.g. "athina" for "αθηνα"
+    pub greek: bool,
+}
+
+impl Default for Transliteration {
+    fn default() -> Transliteration {
+        Transliteration {
+            pinyin: true,
+            pinyin_initials: true,
+            romaji: true,
+            diacritics: true,
+            cyrillic: true,
+            greek: true,
+        }
+    }
+}
+
+const DIACRITICS: &[(&str, &str)] = &[
+    ("àáâãäåāăą", "a"), ("çćĉċč", "c"), ("ďđð", "d"), ("èéêëēĕėęě", "e"),
+    ("ĝğġģ", "g"), ("ĥħ", "h"), ("ìíîïĩīĭįı", "i"), ("ĵ", "j"), ("ķ", "k"),
+    ("ĺļľŀł", "l"), ("ñńņňŉ", "n"), ("òóôõöøōŏő", "o"), ("ŕŗř", "r"),
+    ("śŝşšș", "s"), ("ţťŧț", "t"), ("ùúûüũūŭůűų", "u"), ("ŵ", "w"),
+    ("ýÿŷ", "y"), ("źżž", "z"), ("ß", "ss"), ("æ", "ae"), ("œ", "oe"), ("þ", "th"),
+];
+
+const CYRILLIC: &[(char, &str)] = &[
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ё', "yo"),
+    ('ж', "zh"), ('з', "z"), ('и', "i"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"),
+    ('н', "n"), ('о', "o"), ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"),
+    ('ф', "f"), ('х', "kh"), ('ц', "ts"), ('ч', "ch"), ('ш', "sh"), ('щ', "shch"), ('ъ', ""),
+    ('ы', "y"), ('ь', ""), ('э', "e"), ('ю', "yu"), ('я', "ya"),
+    ('і', "i"), ('ї', "yi"), ('є', "ye"), ('ґ', "g"), ('ў', "u"),
+];
+
+const GREEK: &[(char, &str)] = &[
+    ('α', "a"), ('β', "v"), ('γ', "g"), ('δ', "d"), ('ε', "e"), ('ζ', "z"), ('η', "i"),
+    ('θ', "th"), ('ι', "i"), ('κ', "k"), ('λ', "l"), ('μ', "m"), ('ν', "n"), ('ξ', "x"),
+    ('ο', "o"), ('π', "p"), ('ρ', "r"), ('σ', "s"), ('ς', "s"), ('τ', "t"), ('υ', "y"),
+    ('φ', "f"), ('χ', "ch"), ('ψ', "ps"), ('ω', "o"),
+    ('ά', "a"), ('έ', "e"), ('ή', "i"), ('ί', "i"), ('ό', "o"), ('ύ', "y"), ('ώ', "o"),
+    ('ϊ', "i"), ('ϋ', "y"), ('ΐ', "i"), ('ΰ', "y"),
+];
+
+/// Romaji of hiragana from U+3041 to U+3096, katakana are offset by 0x60
+const KANA_START: u32 = 0x3041;
+const KATAKANA_OFFSET: u32 = 0x60;
+const ROMAJI: [&str; 86] = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o",
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go",
+    "sa", "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo",
+    "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to", "do",
+    "na", "ni", "nu", "ne", "no",
+    "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu", "he", "be", "pe", "ho", "bo", "po",
+    "ma", "mi", "mu", "me", "mo",
+    "ya", "ya", "yu", "yu", "yo", "yo",
+    "ra", "ri", "ru", "re", "ro",
+    "wa", "wa", "wi", "we", "wo", "n", "vu", "ka", "ke",
+];
+const SMALL_TSU: char = 'っ';
+const SMALL_YA: &str = "ゃゅょ";
+const SMALL_VOWELS: &str = "ぁぃぅぇぉ";
+
+/// Index of kana (as hiragana) in `ROMAJI`
+fn kana_index(c: char) -> Option<usize> {
+    let mut code = c as u32;
+    if code >= KANA_START + KATAKANA_OFFSET {
+        code -= KATAKANA_OFFSET;
+    }
+    if code >= KANA_START && code < KANA_START + ROMAJI.len() as u32 {
+        Some((code - KANA_START) as usize)
+    } else {
+        None
+    }
+}
+
+/// Same kana as hiragana
+fn hiragana(c: char) -> Option<char> {
+    kana_index(c).and_then(|idx| std::char::from_u32(KANA_START + idx as u32))
+}
+
+/// Romaji of kana, combined with the following small kana (e.g. "kya", "fa"),
+/// return the text and whether `next` is consumed
+fn romaji(c: char, next: Option<char>) -> Option<(String, bool)> {
+    let text = ROMAJI[kana_index(c)?];
+    if hiragana(c) == Some(SMALL_TSU) {
+        // doubles the following consonant
+        let consonant = next.and_then(kana_index).and_then(|x| ROMAJI[x].chars().next())
+            .filter(|x| !"aiueon".contains(*x));
+        return Some((consonant.map(|x| x.to_string()).unwrap_or_default(), false));
+    }
+    if let Some(small) = next.and_then(hiragana) {
+        let small_text = ROMAJI[kana_index(small).unwrap()];
+        if SMALL_YA.contains(small) && text.len() > 1 && text.ends_with('i') {
+            let base = &text[..text.len() - 1];
+            let combined = if base.ends_with("sh") || base.ends_with("ch") || base == "j" {
+                format!("{}{}", base, &small_text[1..])
+            } else {
+                format!("{}{}", base, small_text)
+            };
+            return Some((combined, true));
+        }
+        if SMALL_VOWELS.contains(small) && text.len() > 1 {
+            return Some((format!("{}{}", &text[..text.len() - 1], small_text), true));
+        }
+    }
+    Some((text.into(), false))
+}
+
+/// Transliterate non-ASCII char `c` (followed by `next`) to latin letters, except pinyin,
+/// return the text (may be empty) and whether `next` is consumed, None if not supported
+pub(crate) fn transliterate(c: char, next: Option<char>, options: &Transliteration) -> Option<(String, bool)> {
+    if options.romaji {
+        if let Some(ret) = romaji(c, next) {
+            return Some(ret);
+        }
+    }
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    let mut text: Option<&str> = None;
+    if options.cyrillic {
+        text = text.or_else(|| CYRILLIC.iter().find(|x| x.0 == lower).map(|x| x.1));
+    }
+    if options.greek {
+        text = text.or_else(|| GREEK.iter().find(|x| x.0 == lower).map(|x| x.1));
+    }
+    if options.diacritics {
+        text = text.or_else(|| DIACRITICS.iter().find(|x| x.0.contains(lower)).map(|x| x.1));
+    }
+    text.map(|text| {
+        // keep the case for word boundaries, e.g. "Ölfarbe" -> "Olfarbe"
+        let mut chars = text.chars();
+        let ret = match chars.next() {
+            Some(first) if c.is_uppercase() => first.to_uppercase().chain(chars).collect(),
+            _ => text.into(),
+        };
+        (ret, false)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latin(text: &str) -> String {
+        let options = Transliteration::default();
+        let chars: Vec<char> = text.chars().collect();
+        let mut ret = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (text, consumed) = transliterate(chars[i], chars.get(i + 1).cloned(), &options)
+                .unwrap_or((chars[i].to_string(), false));
+            ret += &text;
+            i += if consumed { 2 } else { 1 };
+        }
+        ret
+    }
+
+    #[test]
+    fn transliterate_test() {
+        assert_eq!(latin("Café Straße Ølberg"), "Cafe Strasse Olberg");
+        assert_eq!(latin("Москва"), "Moskva");
+        assert_eq!(latin("Αθήνα"), "Athina");
+        assert_eq!(latin("カメラ"), "kamera");
+        assert_eq!(latin("きょうと"), "kyouto");
+        assert_eq!(latin("しゃしん"), "shashin");
+        assert_eq!(latin("ファイル"), "fairu");
+        assert_eq!(latin("ざっし"), "zasshi");
+        assert_eq!(transliterate('é', None, &Transliteration { diacritics: false, .. Transliteration::default() }),
+                   None);
+    }
+}